@@ -0,0 +1,442 @@
+//! Persistent on-disk cache of HTTP sources.
+//!
+//! Every cached url is stored in two files named by a hash of the url: `<key>.data`, a sparse
+//! file holding the downloaded chunks at their offsets, and `<key>.meta`, a small text file with
+//! the url, the validators of the response, the last access time and a bitmap of the stored
+//! chunks.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::url_source_buff::CHUNK_SIZE;
+
+/// Disk cache of HTTP sources with a known length, shared by all sources of a player.
+///
+/// Entries are keyed by url and validated with the `ETag`, `Last-Modified` and
+/// `Content-Length` response headers, a changed resource is downloaded again. When the
+/// cached audio grows over `max_size` bytes, least recently used entries are removed.
+///
+/// ```no_run
+/// use url2audio::{DiskCache, Player};
+///
+/// let cache = DiskCache::new("/tmp/url2audio-cache", 500 * 1024 * 1024);
+/// let player = Player::builder().cache(cache).build();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DiskCache(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    max_size: u64,
+    /// Entries opened by sources, by key. Sources of the same url share the entry, entries in
+    /// use aren't evicted. The lock serializes metadata writes and eviction.
+    open: Mutex<HashMap<String, Weak<SharedEntry>>>,
+}
+
+/// Response headers a cache entry is valid for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub len: u64,
+}
+
+/// Contents of a `.meta` file.
+#[derive(Debug, Default, PartialEq)]
+struct EntryMeta {
+    url: String,
+    validators: Validators,
+    /// Last use, nanoseconds since the Unix epoch.
+    accessed: u64,
+    /// Stored chunks.
+    chunks: Vec<bool>,
+}
+
+/// Cache entry of one url, opened by a source.
+pub(crate) struct CacheEntry {
+    cache: DiskCache,
+    key: String,
+    shared: Arc<SharedEntry>,
+}
+
+/// State of an entry shared by the sources which opened it.
+#[derive(Debug)]
+struct SharedEntry {
+    validators: Validators,
+    files: Mutex<EntryFiles>,
+}
+
+#[derive(Debug)]
+struct EntryFiles {
+    data: File,
+    meta: EntryMeta,
+}
+
+impl DiskCache {
+    /// Cache in `dir` (created when first used) holding at most `max_size` bytes of audio.
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        DiskCache(Arc::new(Inner {
+            dir: dir.into(),
+            max_size,
+            open: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.0.dir
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.0.max_size
+    }
+
+    /// Bytes of audio currently stored.
+    pub fn size(&self) -> u64 {
+        let _open = self.lock();
+        self.entries().iter().map(|(_, meta)| meta.size()).sum()
+    }
+
+    /// Remove all entries, except those in use by a source.
+    pub fn clear(&self) {
+        let open = self.lock();
+        for (key, _) in self.entries() {
+            if !in_use(&open, &key) {
+                self.remove(&key);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Weak<SharedEntry>>> {
+        self.0.open.lock().unwrap()
+    }
+
+    /// Open the entry of `url`. Chunks stored for another version of the resource are
+    /// discarded. If another source has the entry open, it is shared; it can't be opened for
+    /// another version of the resource meanwhile.
+    pub(crate) fn open(&self, url: &str, validators: Validators) -> io::Result<CacheEntry> {
+        let mut open = self.lock();
+        open.retain(|_, entry| entry.strong_count() > 0);
+        let key = entry_key(url);
+        if let Some(shared) = open.get(&key).and_then(Weak::upgrade) {
+            if shared.validators != validators {
+                return Err(io::Error::other("cache entry is in use for another version"));
+            }
+            return Ok(CacheEntry {
+                cache: self.clone(),
+                key,
+                shared,
+            });
+        }
+        fs::create_dir_all(&self.0.dir)?;
+
+        let n_chunks = (validators.len as usize).div_ceil(CHUNK_SIZE);
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path(&key, "data"))?;
+        let stored = self.read_meta(&key).filter(|meta| {
+            meta.url == url && meta.validators == validators && meta.chunks.len() == n_chunks
+        });
+        let entry_validators = validators.clone();
+        let mut meta = match stored {
+            Some(meta) if data.metadata()?.len() == validators.len => meta,
+            _ => {
+                // Sparse file, chunks are written at their offsets.
+                data.set_len(0)?;
+                data.set_len(validators.len)?;
+                EntryMeta {
+                    url: url.to_string(),
+                    validators,
+                    accessed: 0,
+                    chunks: vec![false; n_chunks],
+                }
+            }
+        };
+        meta.accessed = now();
+        fs::write(self.path(&key, "meta"), meta.to_text())?;
+
+        let shared = Arc::new(SharedEntry {
+            validators: entry_validators,
+            files: Mutex::new(EntryFiles { data, meta }),
+        });
+        open.insert(key.clone(), Arc::downgrade(&shared));
+        Ok(CacheEntry {
+            cache: self.clone(),
+            key,
+            shared,
+        })
+    }
+
+    fn path(&self, key: &str, extension: &str) -> PathBuf {
+        self.0.dir.join(format!("{}.{}", key, extension))
+    }
+
+    fn read_meta(&self, key: &str) -> Option<EntryMeta> {
+        EntryMeta::parse(&fs::read_to_string(self.path(key, "meta")).ok()?)
+    }
+
+    /// Keys and metadata of all entries.
+    fn entries(&self) -> Vec<(String, EntryMeta)> {
+        let Ok(dir) = fs::read_dir(&self.0.dir) else {
+            return vec![];
+        };
+        dir.filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let key = name.strip_suffix(".meta")?.to_string();
+            let meta = self.read_meta(&key)?;
+            Some((key, meta))
+        })
+        .collect()
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key, "meta"));
+        let _ = fs::remove_file(self.path(key, "data"));
+    }
+
+    /// Remove least recently used entries not in use until the cache fits `max_size`.
+    fn evict(&self, open: &HashMap<String, Weak<SharedEntry>>) {
+        let mut entries = self.entries();
+        let mut size: u64 = entries.iter().map(|(_, meta)| meta.size()).sum();
+        entries.sort_by_key(|(_, meta)| meta.accessed);
+        for (key, meta) in entries {
+            if size <= self.0.max_size {
+                break;
+            }
+            if !in_use(open, &key) {
+                self.remove(&key);
+                size -= meta.size();
+            }
+        }
+    }
+}
+
+fn in_use(open: &HashMap<String, Weak<SharedEntry>>, key: &str) -> bool {
+    open.get(key).is_some_and(|entry| entry.strong_count() > 0)
+}
+
+impl CacheEntry {
+    fn files(&self) -> MutexGuard<'_, EntryFiles> {
+        self.shared.files.lock().unwrap()
+    }
+
+    /// Keys of the stored chunks.
+    pub fn chunks(&self) -> Vec<usize> {
+        let files = self.files();
+        files.meta.chunks.iter().enumerate().filter(|(_, &stored)| stored).map(|(key, _)| key).collect()
+    }
+
+    pub fn contains(&self, key: usize) -> bool {
+        self.files().meta.chunks.get(key).is_some_and(|&stored| stored)
+    }
+
+    pub fn read_chunk(&mut self, key: usize) -> io::Result<Vec<u8>> {
+        let mut files = self.files();
+        let mut chunk = vec![0u8; files.meta.chunk_len(key) as usize];
+        files.data.seek(SeekFrom::Start((key * CHUNK_SIZE) as u64))?;
+        files.data.read_exact(&mut chunk)?;
+        Ok(chunk)
+    }
+
+    /// Store a downloaded chunk. A chunk which doesn't fit the size limit isn't stored,
+    /// other entries are evicted to make room for one which does.
+    pub fn write_chunk(&mut self, key: usize, chunk: &[u8]) -> io::Result<()> {
+        // Lock order is the cache, then the entry.
+        let open = self.cache.lock();
+        let mut files = self.files();
+        let meta = &files.meta;
+        if key >= meta.chunks.len()
+            || meta.chunks[key]
+            || chunk.len() as u64 != meta.chunk_len(key)
+            || meta.size() + chunk.len() as u64 > self.cache.0.max_size
+        {
+            return Ok(());
+        }
+        files.data.seek(SeekFrom::Start((key * CHUNK_SIZE) as u64))?;
+        files.data.write_all(chunk)?;
+        files.meta.chunks[key] = true;
+        files.meta.accessed = now();
+
+        fs::write(self.cache.path(&self.key, "meta"), files.meta.to_text())?;
+        drop(files);
+        self.cache.evict(&open);
+        Ok(())
+    }
+}
+
+impl EntryMeta {
+    fn chunk_len(&self, key: usize) -> u64 {
+        let begin = (key * CHUNK_SIZE) as u64;
+        (CHUNK_SIZE as u64).min(self.validators.len.saturating_sub(begin))
+    }
+
+    /// Bytes of the stored chunks.
+    fn size(&self) -> u64 {
+        (0..self.chunks.len()).filter(|&key| self.chunks[key]).map(|key| self.chunk_len(key)).sum()
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut meta = EntryMeta::default();
+        for line in text.lines() {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "url" => meta.url = value.to_string(),
+                "etag" => meta.validators.etag = Some(value.to_string()),
+                "last-modified" => meta.validators.last_modified = Some(value.to_string()),
+                "length" => meta.validators.len = value.parse().ok()?,
+                "accessed" => meta.accessed = value.parse().ok()?,
+                "chunks" => meta.chunks = parse_bitmap(value)?,
+                _ => {}
+            }
+        }
+        let n_chunks = (meta.validators.len as usize).div_ceil(CHUNK_SIZE);
+        meta.chunks.resize(n_chunks, false);
+        Some(meta)
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("url {}\n", self.url);
+        if let Some(etag) = &self.validators.etag {
+            text += &format!("etag {}\n", etag);
+        }
+        if let Some(last_modified) = &self.validators.last_modified {
+            text += &format!("last-modified {}\n", last_modified);
+        }
+        text += &format!("length {}\n", self.validators.len);
+        text += &format!("accessed {}\n", self.accessed);
+        text += &format!("chunks {}\n", format_bitmap(&self.chunks));
+        text
+    }
+}
+
+/// Hex string, bit `i % 8` of byte `i / 8` set for stored chunk `i`.
+fn format_bitmap(chunks: &[bool]) -> String {
+    chunks
+        .chunks(8)
+        .map(|bits| {
+            let byte = bits.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i));
+            format!("{:02x}", byte)
+        })
+        .collect()
+}
+
+fn parse_bitmap(hex: &str) -> Option<Vec<bool>> {
+    let bytes = hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(bytes.iter().flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0)).collect())
+}
+
+/// File name of the entry of `url`. FNV-1a, stable across builds unlike `DefaultHasher`.
+fn entry_key(url: &str) -> String {
+    let hash = url
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[test]
+fn stores_and_validates_chunks() {
+    let dir = crate::test_util::temp_path("cache-validate");
+    let cache = DiskCache::new(&dir, 10 * CHUNK_SIZE as u64);
+    let validators = Validators {
+        etag: Some("\"v1\"".to_string()),
+        last_modified: None,
+        len: 2 * CHUNK_SIZE as u64 + 10,
+    };
+
+    let mut entry = cache.open("http://host/a.mp3", validators.clone()).unwrap();
+    entry.write_chunk(0, &[1u8; CHUNK_SIZE]).unwrap();
+    entry.write_chunk(2, &[3u8; 10]).unwrap();
+    drop(entry);
+
+    let mut entry = cache.open("http://host/a.mp3", validators.clone()).unwrap();
+    assert_eq!(entry.chunks(), [0, 2]);
+    assert_eq!(entry.read_chunk(2).unwrap(), [3u8; 10]);
+    assert_eq!(cache.size(), CHUNK_SIZE as u64 + 10);
+    drop(entry);
+
+    // Changed resource.
+    let changed = Validators {
+        etag: Some("\"v2\"".to_string()),
+        ..validators
+    };
+    let entry = cache.open("http://host/a.mp3", changed).unwrap();
+    assert!(entry.chunks().is_empty());
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let dir = crate::test_util::temp_path("cache-lru");
+    let cache = DiskCache::new(&dir, 2 * CHUNK_SIZE as u64);
+    let validators = Validators {
+        len: CHUNK_SIZE as u64,
+        ..Default::default()
+    };
+    let chunk = vec![0u8; CHUNK_SIZE];
+
+    for url in ["http://host/a", "http://host/b"] {
+        cache.open(url, validators.clone()).unwrap().write_chunk(0, &chunk).unwrap();
+    }
+    // Use `a` again, `b` becomes the least recently used.
+    drop(cache.open("http://host/a", validators.clone()).unwrap());
+    cache.open("http://host/c", validators.clone()).unwrap().write_chunk(0, &chunk).unwrap();
+
+    assert_eq!(cache.size(), 2 * CHUNK_SIZE as u64);
+    assert!(cache.open("http://host/a", validators.clone()).unwrap().contains(0));
+    assert!(!cache.open("http://host/b", validators).unwrap().contains(0));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn shares_entries_in_use() {
+    let dir = crate::test_util::temp_path("cache-shared");
+    let cache = DiskCache::new(&dir, CHUNK_SIZE as u64);
+    let validators = Validators {
+        len: CHUNK_SIZE as u64,
+        ..Default::default()
+    };
+    let chunk = vec![0u8; CHUNK_SIZE];
+
+    // Two sources of the same url see each other's chunks.
+    let mut first = cache.open("http://host/a", validators.clone()).unwrap();
+    let mut second = cache.open("http://host/a", validators.clone()).unwrap();
+    first.write_chunk(0, &chunk).unwrap();
+    assert!(second.contains(0));
+    second.write_chunk(0, &chunk).unwrap();
+    let changed = Validators {
+        etag: Some("\"v2\"".to_string()),
+        ..validators.clone()
+    };
+    assert!(cache.open("http://host/a", changed).is_err());
+
+    // An entry in use isn't evicted for another one.
+    let mut other = cache.open("http://host/b", validators.clone()).unwrap();
+    other.write_chunk(0, &chunk).unwrap();
+    assert!(first.contains(0));
+    drop((first, second));
+    assert_eq!(cache.open("http://host/a", validators.clone()).unwrap().chunks(), [0]);
+    drop(other);
+    cache.open("http://host/c", validators.clone()).unwrap().write_chunk(0, &chunk).unwrap();
+    assert!(cache.open("http://host/a", validators).unwrap().chunks().is_empty());
+
+    let _ = fs::remove_dir_all(dir);
+}
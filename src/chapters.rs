@@ -0,0 +1,558 @@
+//! Chapters of ID3v2 tags (`CHAP`/`CTOC` frames) and MP4 files (QuickTime chapter track or
+//! Nero `chpl` box). Symphonia skips both, so they are read from the source before probing.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use crate::metadata::Picture;
+
+/// Tags or `moov` boxes larger than this are not read for chapters.
+const MAX_READ_LEN: u64 = 32 << 20;
+
+/// Chapter of the current track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub title: Option<String>,
+    /// Start in seconds.
+    pub start: f64,
+    /// End in seconds, `None` for the last chapter of a track with unknown duration.
+    pub end: Option<f64>,
+    /// Link of the chapter (`WXXX` frame).
+    pub url: Option<String>,
+    pub image: Option<Picture>,
+}
+
+/// Chapters of `source`, ordered by start. Empty if it has none or they can't be read.
+/// The read position is left anywhere.
+pub(crate) fn read_chapters<R: Read + Seek + ?Sized>(source: &mut R) -> Vec<Chapter> {
+    let mut head = [0u8; 12];
+    if source.rewind().and_then(|_| source.read_exact(&mut head)).is_err() {
+        return Vec::new();
+    }
+    let mut chapters = if head.starts_with(b"ID3") {
+        id3_chapters(source, &head).unwrap_or_default()
+    } else if &head[4..8] == b"ftyp" {
+        mp4_chapters(source).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    // Chapters without an end last until the next one.
+    let starts: Vec<f64> = chapters.iter().skip(1).map(|c| c.start).collect();
+    for (chapter, next) in chapters.iter_mut().zip(starts) {
+        chapter.end = chapter.end.or(Some(next));
+    }
+    chapters
+}
+
+/// Index of the chapter playing at `position`.
+pub(crate) fn chapter_at(chapters: &[Chapter], position: f64) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|c| c.start <= position)
+        .filter(|&i| chapters[i].end.is_none_or(|end| position < end) || i + 1 == chapters.len())
+}
+
+fn read_vec<R: Read + ?Sized>(source: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_READ_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "chapters: too large"));
+    }
+    let mut data = vec![0; len as usize];
+    source.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn syncsafe(data: &[u8]) -> u32 {
+    data.iter().take(4).fold(0, |n, &b| (n << 7) | (b & 0x7f) as u32)
+}
+
+/// Remove the `0x00` inserted after `0xff` by unsynchronisation.
+fn resync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if !(b == 0 && i > 0 && data[i - 1] == 0xff) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn id3_chapters<R: Read + Seek + ?Sized>(source: &mut R, head: &[u8; 12]) -> io::Result<Vec<Chapter>> {
+    let (version, flags) = (head[3], head[5]);
+    // ID3v2.2 has no chapter frames.
+    if !(3..=4).contains(&version) {
+        return Ok(Vec::new());
+    }
+    source.seek(SeekFrom::Start(10))?;
+    let mut tag = read_vec(source, syncsafe(&head[6..10]) as u64)?;
+    if flags & 0x80 != 0 && version == 3 {
+        tag = resync(&tag);
+    }
+    if flags & 0x40 != 0 {
+        // Extended header, its size includes the size field in v2.4 only.
+        let len = match version {
+            3 => be_u32(&tag, 0).unwrap_or(0) as usize + 4,
+            _ => syncsafe(&tag) as usize,
+        };
+        tag.drain(..len.min(tag.len()));
+    }
+
+    let frames = id3_frames(&tag, version);
+    let mut chapters: Vec<(&str, Chapter)> = Vec::new();
+    let mut toc: Option<Vec<&str>> = None;
+    for (id, body) in &frames {
+        match id {
+            b"CHAP" => {
+                if let Some(chapter) = parse_chap(body, version) {
+                    chapters.push(chapter);
+                }
+            }
+            // The top level table of contents gives the chapters to play and their order.
+            b"CTOC" if toc.is_none() => toc = parse_ctoc(body, &frames),
+            _ => {}
+        }
+    }
+    Ok(match toc {
+        Some(ids) => ids
+            .into_iter()
+            .filter_map(|id| chapters.iter().find(|(chap_id, _)| *chap_id == id).map(|(_, c)| c.clone()))
+            .collect(),
+        None => chapters.into_iter().map(|(_, c)| c).collect(),
+    })
+}
+
+/// `(id, body)` of the frames in `data`.
+fn id3_frames(mut data: &[u8], version: u8) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut frames = Vec::new();
+    while data.len() >= 10 && data[0] != 0 {
+        let id: [u8; 4] = data[..4].try_into().unwrap();
+        let len = match version {
+            3 => be_u32(data, 4).unwrap_or(0),
+            _ => syncsafe(&data[4..8]),
+        } as usize;
+        let flags = u16::from_be_bytes([data[8], data[9]]);
+        let Some(body) = data.get(10..10 + len) else {
+            break;
+        };
+        data = &data[10 + len..];
+        let mut body = body.to_vec();
+        if version == 4 {
+            if flags & 0x0002 != 0 {
+                body = resync(&body);
+            }
+            if flags & 0x0001 != 0 {
+                // Data length indicator.
+                body.drain(..4.min(body.len()));
+            }
+        }
+        frames.push((id, body));
+    }
+    frames
+}
+
+/// Null terminated string of `encoding` at the start of `data` and the rest after it.
+fn id3_string(data: &[u8], encoding: u8) -> (String, &[u8]) {
+    let wide = matches!(encoding, 1 | 2);
+    let end = if wide {
+        data.chunks_exact(2).position(|c| c == [0, 0]).map(|i| i * 2)
+    } else {
+        data.iter().position(|&b| b == 0)
+    };
+    let (text, rest) = match end {
+        Some(end) => (&data[..end], &data[end + if wide { 2 } else { 1 }..]),
+        None => (data, &[][..]),
+    };
+    let text = match encoding {
+        0 => text.iter().map(|&b| b as char).collect(),
+        1 | 2 => {
+            let big_endian = encoding == 2 || text.starts_with(&[0xfe, 0xff]);
+            let units = text.chunks_exact(2).map(|c| match big_endian {
+                true => u16::from_be_bytes([c[0], c[1]]),
+                false => u16::from_le_bytes([c[0], c[1]]),
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .filter(|&c| c != '\u{feff}')
+                .collect()
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    (text, rest)
+}
+
+fn parse_chap(body: &[u8], version: u8) -> Option<(&str, Chapter)> {
+    let id_end = body.iter().position(|&b| b == 0)?;
+    let id = std::str::from_utf8(&body[..id_end]).ok()?;
+    let times = body.get(id_end + 1..id_end + 17)?;
+    let (start, end) = (be_u32(times, 0)?, be_u32(times, 4)?);
+    let mut chapter = Chapter {
+        title: None,
+        start: start as f64 / 1000.0,
+        end: (end != u32::MAX && end > start).then(|| end as f64 / 1000.0),
+        url: None,
+        image: None,
+    };
+    for (frame, data) in id3_frames(&body[id_end + 17..], version) {
+        let Some((&encoding, rest)) = data.split_first() else {
+            continue;
+        };
+        match &frame {
+            b"TIT2" => chapter.title = Some(id3_string(rest, encoding).0).filter(|t| !t.is_empty()),
+            b"WXXX" => {
+                let (_description, url) = id3_string(rest, encoding);
+                chapter.url = Some(id3_string(url, 0).0);
+            }
+            b"APIC" => {
+                let (media_type, rest) = id3_string(rest, 0);
+                let Some((_picture_type, rest)) = rest.split_first() else {
+                    continue;
+                };
+                let (_description, image) = id3_string(rest, encoding);
+                chapter.image = Some(Picture {
+                    media_type,
+                    data: Arc::from(image),
+                });
+            }
+            _ => {}
+        }
+    }
+    Some((id, chapter))
+}
+
+/// Chapter ids of the top level `CTOC` frame, nested tables of contents expanded.
+fn parse_ctoc<'a>(body: &'a [u8], frames: &'a [([u8; 4], Vec<u8>)]) -> Option<Vec<&'a str>> {
+    fn entries(body: &[u8]) -> Option<(u8, Vec<&str>)> {
+        let id_end = body.iter().position(|&b| b == 0)?;
+        let flags = *body.get(id_end + 1)?;
+        let count = *body.get(id_end + 2)? as usize;
+        let mut rest = body.get(id_end + 3..)?;
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            let end = rest.iter().position(|&b| b == 0)?;
+            ids.push(std::str::from_utf8(&rest[..end]).ok()?);
+            rest = &rest[end + 1..];
+        }
+        Some((flags, ids))
+    }
+    fn expand<'a>(ids: Vec<&'a str>, frames: &'a [([u8; 4], Vec<u8>)], depth: usize, out: &mut Vec<&'a str>) {
+        for id in ids {
+            let nested = frames
+                .iter()
+                .filter(|(frame, _)| frame == b"CTOC")
+                .find(|(_, body)| body.starts_with(id.as_bytes()) && body.get(id.len()) == Some(&0));
+            match nested.and_then(|(_, body)| entries(body)) {
+                Some((_, nested)) if depth < 4 => expand(nested, frames, depth + 1, out),
+                _ => out.push(id),
+            }
+        }
+    }
+
+    let (flags, ids) = entries(body)?;
+    // Only the top level table (flag 0x02) lists the chapters.
+    if flags & 0x02 == 0 {
+        return None;
+    }
+    let mut out = Vec::new();
+    expand(ids, frames, 0, &mut out);
+    Some(out)
+}
+
+/// Boxes `(type, body)` in `data`.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = be_u32(data, 0)? as u64;
+        let kind = data.get(4..8)?;
+        let (header, size) = match size {
+            0 => (8, data.len() as u64),
+            1 => (16, be_u64(data, 8)?),
+            size => (8, size),
+        };
+        let body = data.get(header..usize::try_from(size).ok()?)?;
+        data = &data[size as usize..];
+        Some((kind, body))
+    })
+}
+
+/// Body of the box at `path` below `data`.
+fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    let (_, body) = boxes(data).find(|(kind, _)| kind == first)?;
+    if rest.is_empty() {
+        Some(body)
+    } else {
+        find_box(body, rest)
+    }
+}
+
+fn mp4_chapters<R: Read + Seek + ?Sized>(source: &mut R) -> io::Result<Vec<Chapter>> {
+    // Top level boxes up to `moov`, which is often at the end.
+    let mut pos = 0;
+    let moov = loop {
+        source.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        source.read_exact(&mut header[..8])?;
+        let (header_len, size) = match be_u32(&header, 0).unwrap_or(0) {
+            0 => break None,
+            1 => {
+                source.read_exact(&mut header[8..])?;
+                (16, be_u64(&header, 8).unwrap_or(0))
+            }
+            size => (8, size as u64),
+        };
+        if size < header_len {
+            break None;
+        }
+        if &header[4..8] == b"moov" {
+            break Some(read_vec(source, size - header_len)?);
+        }
+        pos += size;
+    };
+    let Some(moov) = moov else {
+        return Ok(Vec::new());
+    };
+
+    match chapter_track(source, &moov)? {
+        Some(chapters) => Ok(chapters),
+        None => Ok(find_box(&moov, &[b"udta", b"chpl"]).and_then(parse_chpl).unwrap_or_default()),
+    }
+}
+
+/// Nero chapters: start in 100 ns units and title.
+fn parse_chpl(body: &[u8]) -> Option<Vec<Chapter>> {
+    let mut at = if body.first()? != &0 { 8 } else { 4 };
+    let count = *body.get(at)?;
+    at += 1;
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let start = be_u64(body, at)?;
+        let len = *body.get(at + 8)? as usize;
+        let title = String::from_utf8_lossy(body.get(at + 9..at + 9 + len)?).into_owned();
+        at += 9 + len;
+        chapters.push(Chapter {
+            title: Some(title).filter(|t| !t.is_empty()),
+            start: start as f64 / 10_000_000.0,
+            end: None,
+            url: None,
+            image: None,
+        });
+    }
+    Some(chapters)
+}
+
+/// Chapters of the QuickTime text track referenced by a `tref/chap` box, `None` if there
+/// is none.
+fn chapter_track<R: Read + Seek + ?Sized>(source: &mut R, moov: &[u8]) -> io::Result<Option<Vec<Chapter>>> {
+    let traks: Vec<&[u8]> = boxes(moov).filter(|(kind, _)| kind == b"trak").map(|(_, body)| body).collect();
+    let track_id = |trak: &[u8]| {
+        let tkhd = find_box(trak, &[b"tkhd"])?;
+        be_u32(tkhd, if tkhd.first()? == &1 { 20 } else { 12 })
+    };
+    let referenced: Vec<u32> = traks
+        .iter()
+        .filter_map(|trak| find_box(trak, &[b"tref", b"chap"]))
+        .flat_map(|chap| chap.chunks_exact(4).map(|id| u32::from_be_bytes(id.try_into().unwrap())))
+        .collect();
+    let is_text = |trak: &[u8]| {
+        find_box(trak, &[b"mdia", b"hdlr"]).and_then(|hdlr| hdlr.get(8..12)).is_some_and(|t| t == b"text" || t == b"sbtl")
+    };
+    let Some(trak) = traks
+        .into_iter()
+        .find(|trak| track_id(trak).is_some_and(|id| referenced.contains(&id)) && is_text(trak))
+    else {
+        return Ok(None);
+    };
+    let Some(samples) = text_samples(trak) else {
+        return Ok(None);
+    };
+
+    let mut chapters = Vec::with_capacity(samples.len());
+    for (start, duration, offset, size) in samples {
+        source.seek(SeekFrom::Start(offset))?;
+        let sample = read_vec(source, size as u64)?;
+        let len = sample.get(..2).map_or(0, |l| u16::from_be_bytes([l[0], l[1]]) as usize);
+        let text = sample.get(2..2 + len).unwrap_or_default();
+        let title = if text.starts_with(&[0xfe, 0xff]) || text.starts_with(&[0xff, 0xfe]) {
+            id3_string(text, 1).0
+        } else {
+            String::from_utf8_lossy(text).into_owned()
+        };
+        chapters.push(Chapter {
+            title: Some(title).filter(|t| !t.is_empty()),
+            start,
+            end: Some(start + duration),
+            url: None,
+            image: None,
+        });
+    }
+    Ok(Some(chapters))
+}
+
+/// `(start, duration, offset, size)` of the samples of a track, times in seconds.
+fn text_samples(trak: &[u8]) -> Option<Vec<(f64, f64, u64, u32)>> {
+    let mdhd = find_box(trak, &[b"mdia", b"mdhd"])?;
+    let timescale = be_u32(mdhd, if mdhd.first()? == &1 { 20 } else { 12 })?.max(1) as f64;
+    let stbl = find_box(trak, &[b"mdia", b"minf", b"stbl"])?;
+    // Entries of a table box with a count after the version and flags.
+    let entries = |name: &[u8; 4], width: usize| -> Option<Vec<&[u8]>> {
+        let body = find_box(stbl, &[name])?;
+        let count = be_u32(body, 4)? as usize;
+        Some(body.get(8..)?.chunks_exact(width).take(count).collect())
+    };
+
+    let durations: Vec<u32> = entries(b"stts", 8)?
+        .into_iter()
+        .flat_map(|e| std::iter::repeat_n(be_u32(e, 4).unwrap_or(0), be_u32(e, 0).unwrap_or(0) as usize))
+        .collect();
+    let stsz = find_box(stbl, &[b"stsz"])?;
+    let (size, count) = (be_u32(stsz, 4)?, be_u32(stsz, 8)? as usize);
+    let sizes: Vec<u32> = match size {
+        0 => stsz.get(12..)?.chunks_exact(4).take(count).map(|s| be_u32(s, 0).unwrap_or(0)).collect(),
+        size => vec![size; count],
+    };
+    let chunk_offsets: Vec<u64> = match entries(b"stco", 4) {
+        Some(offsets) => offsets.into_iter().filter_map(|o| be_u32(o, 0).map(u64::from)).collect(),
+        None => entries(b"co64", 8)?.into_iter().filter_map(|o| be_u64(o, 0)).collect(),
+    };
+    // (first chunk, samples per chunk)
+    let stsc: Vec<(usize, usize)> = entries(b"stsc", 12)?
+        .into_iter()
+        .filter_map(|e| Some((be_u32(e, 0)? as usize, be_u32(e, 4)? as usize)))
+        .collect();
+
+    let mut samples = Vec::with_capacity(sizes.len());
+    let mut time = 0u64;
+    let mut sample = 0;
+    for (chunk, &chunk_offset) in chunk_offsets.iter().enumerate() {
+        let per_chunk = stsc.iter().rev().find(|(first, _)| *first <= chunk + 1).map_or(1, |(_, n)| *n);
+        let mut offset = chunk_offset;
+        for _ in 0..per_chunk {
+            let (Some(&size), Some(&duration)) = (sizes.get(sample), durations.get(sample)) else {
+                return Some(samples);
+            };
+            samples.push((time as f64 / timescale, duration as f64 / timescale, offset, size));
+            time += duration as u64;
+            offset += size as u64;
+            sample += 1;
+        }
+    }
+    Some(samples)
+}
+
+/// ID3v2.4 tag with a top level table of contents listing `chapters`
+/// (id, start ms, end ms, title).
+#[cfg(test)]
+pub(crate) fn id3_tag(chapters: &[(&str, u32, u32, &str)]) -> Vec<u8> {
+    fn frame(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let len = body.len() as u32;
+        let mut frame = id.to_vec();
+        frame.extend((0..4).rev().map(|i| ((len >> (7 * i)) & 0x7f) as u8));
+        frame.extend([0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    let mut frames = Vec::new();
+    let mut toc = b"toc\0\x03".to_vec();
+    toc.push(chapters.len() as u8);
+    for (id, ..) in chapters.iter().rev() {
+        toc.extend_from_slice(id.as_bytes());
+        toc.push(0);
+    }
+    frames.extend(frame(b"CTOC", &toc));
+    for &(id, start, end, title) in chapters {
+        let mut chap = id.as_bytes().to_vec();
+        chap.push(0);
+        for n in [start, end, u32::MAX, u32::MAX] {
+            chap.extend(n.to_be_bytes());
+        }
+        chap.extend(frame(b"TIT2", &[&[3], title.as_bytes()].concat()));
+        chap.extend(frame(b"WXXX", &[&[0, 0], format!("https://example.com/{}", id).as_bytes()].concat()));
+        frames.extend(frame(b"CHAP", &chap));
+    }
+    let len = frames.len() as u32;
+    let mut tag = b"ID3\x04\x00\x00".to_vec();
+    tag.extend((0..4).rev().map(|i| ((len >> (7 * i)) & 0x7f) as u8));
+    tag.extend(frames);
+    tag
+}
+
+#[test]
+fn reads_id3_chapters() {
+    // The table of contents lists the chapters in reverse, they are sorted by start.
+    let mut tag = id3_tag(&[("one", 0, 1500, "Intro"), ("two", 1500, 4000, "Main")]);
+    tag.extend_from_slice(b"audio");
+    let chapters = read_chapters(&mut io::Cursor::new(tag));
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[0].title.as_deref(), Some("Intro"));
+    assert_eq!((chapters[0].start, chapters[0].end), (0.0, Some(1.5)));
+    assert_eq!((chapters[1].start, chapters[1].end), (1.5, Some(4.0)));
+    assert_eq!(chapters[1].url.as_deref(), Some("https://example.com/two"));
+
+    assert_eq!(chapter_at(&chapters, 0.2), Some(0));
+    assert_eq!(chapter_at(&chapters, 1.5), Some(1));
+    assert_eq!(chapter_at(&chapters, 9.0), Some(1));
+    assert!(read_chapters(&mut io::Cursor::new(b"RIFF....WAVE")).is_empty());
+}
+
+#[test]
+fn reads_mp4_chapters() {
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        [&(body.len() as u32 + 8).to_be_bytes()[..], kind, body].concat()
+    }
+    fn full_box(kind: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+        let body: Vec<u8> = [0u32].iter().chain(fields).flat_map(|f| f.to_be_bytes()).collect();
+        mp4_box(kind, &body)
+    }
+
+    // Nero chapters only.
+    let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+    for (start, title) in [(0u64, "Intro"), (25_000_000, "Main")] {
+        chpl.extend(start.to_be_bytes());
+        chpl.push(title.len() as u8);
+        chpl.extend_from_slice(title.as_bytes());
+    }
+    let file = [mp4_box(b"ftyp", b"M4A "), mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"chpl", &chpl)))].concat();
+    let chapters = read_chapters(&mut io::Cursor::new(file));
+    assert_eq!(chapters.len(), 2);
+    assert_eq!((chapters[0].start, chapters[0].end), (0.0, Some(2.5)));
+    assert_eq!((chapters[1].title.as_deref(), chapters[1].end), (Some("Main"), None));
+
+    // QuickTime chapter track, samples in `mdat` before `moov`.
+    let samples: Vec<u8> = ["First", "Second"]
+        .iter()
+        .flat_map(|t| [&(t.len() as u16).to_be_bytes()[..], t.as_bytes()].concat())
+        .collect();
+    let ftyp = mp4_box(b"ftyp", b"M4A ");
+    let mdat_offset = ftyp.len() as u32 + 8;
+    let audio = mp4_box(
+        b"trak",
+        &[full_box(b"tkhd", &[0, 0, 1]), mp4_box(b"tref", &mp4_box(b"chap", &2u32.to_be_bytes()))].concat(),
+    );
+    let stbl = [
+        full_box(b"stts", &[2, 1, 3000, 1, 1000]),
+        full_box(b"stsz", &[0, 2, 7, 8]),
+        full_box(b"stsc", &[1, 1, 2, 1]),
+        full_box(b"stco", &[1, mdat_offset]),
+    ]
+    .concat();
+    let mdia = [
+        full_box(b"mdhd", &[0, 0, 1000, 4000]),
+        full_box(b"hdlr", &[0, u32::from_be_bytes(*b"text")]),
+        mp4_box(b"minf", &mp4_box(b"stbl", &stbl)),
+    ]
+    .concat();
+    let text = mp4_box(b"trak", &[full_box(b"tkhd", &[0, 0, 2]), mp4_box(b"mdia", &mdia)].concat());
+    let file = [ftyp, mp4_box(b"mdat", &samples), mp4_box(b"moov", &[audio, text].concat())].concat();
+    let chapters = read_chapters(&mut io::Cursor::new(file));
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[1].title.as_deref(), Some("Second"));
+    assert_eq!((chapters[1].start, chapters[1].end), (3.0, Some(4.0)));
+}
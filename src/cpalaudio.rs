@@ -1,23 +1,123 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use crate::output::{AudioOutput, AudioOutputError, AudioSink, DeviceId, Result};
 use crate::resampler::Resampler;
+use crate::volume::{GainRamp, SharedGain};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use rb::*;
 use symphonia::core::audio::{AudioBufferRef, RawSample, SampleBuffer, SignalSpec};
-use symphonia::core::conv::{ConvertibleSample, IntoSample};
+use symphonia::core::conv::{ConvertibleSample, FromSample, IntoSample};
 use symphonia::core::units::Duration;
 
-#[allow(dead_code)]
-#[allow(clippy::enum_variant_names)]
-#[derive(Debug)]
-pub enum AudioOutputError {
-    OpenStreamError,
-    PlayStreamError,
-    StreamClosedError,
+/// Output device with its supported stream configurations.
+#[derive(PartialEq, Clone, Debug)]
+pub struct OutputDevice {
+    pub id: DeviceId,
+    /// Default output device of its host.
+    pub is_default: bool,
+    pub configs: Vec<OutputConfig>,
+}
+
+/// Supported output stream configuration range.
+#[derive(PartialEq, Clone, Debug)]
+pub struct OutputConfig {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// Sample format name, e.g. `f32` or `i16`.
+    pub sample_format: String,
+}
+
+/// Names of the audio hosts (backends) available on this system, e.g. `ALSA` or `JACK`.
+pub fn hosts() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| id.name().to_string())
+        .collect()
+}
+
+/// Output devices of all available audio hosts.
+pub fn output_devices() -> Vec<OutputDevice> {
+    let mut devices = vec![];
+    for host_id in cpal::available_hosts() {
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            continue;
+        };
+        let default_name = host.default_output_device().and_then(|d| d.name().ok());
+        let Ok(host_devices) = host.output_devices() else {
+            continue;
+        };
+        for device in host_devices {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+            let configs = device
+                .supported_output_configs()
+                .map(|configs| {
+                    configs
+                        .map(|c| OutputConfig {
+                            channels: c.channels(),
+                            min_sample_rate: c.min_sample_rate().0,
+                            max_sample_rate: c.max_sample_rate().0,
+                            sample_format: c.sample_format().to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            devices.push(OutputDevice {
+                is_default: default_name.as_deref() == Some(name.as_str()),
+                id: DeviceId {
+                    host: host_id.name().to_string(),
+                    name,
+                },
+                configs,
+            });
+        }
+    }
+    devices
+}
+
+/// Find output device by id, or the default output device of the default host.
+fn find_device(id: Option<&DeviceId>) -> Result<cpal::Device> {
+    let Some(id) = id else {
+        return cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| AudioOutputError::DeviceNotFoundError("default output device".to_string()));
+    };
+
+    cpal::available_hosts()
+        .into_iter()
+        .filter(|host_id| host_id.name() == id.host)
+        .filter_map(|host_id| cpal::host_from_id(host_id).ok())
+        .filter_map(|host| host.output_devices().ok())
+        .flatten()
+        .find(|d| d.name().is_ok_and(|name| name == id.name))
+        .ok_or_else(|| AudioOutputError::DeviceNotFoundError(id.name.clone()))
 }
 
-pub type Result<T> = std::result::Result<T, AudioOutputError>;
-pub trait AudioOutput {
-    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
-    fn flush(&mut self);
+/// Sink playing on a system audio device. Default sink of `Player`.
+#[derive(Clone, Debug, Default)]
+pub struct CpalSink {
+    device: Option<DeviceId>,
+}
+
+impl CpalSink {
+    /// Sink playing on `device`, or on the default device of the default host if `None`.
+    pub fn new(device: Option<DeviceId>) -> Self {
+        CpalSink { device }
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn open(&mut self, spec: SignalSpec, duration: Duration, volume: f32) -> Result<Box<dyn AudioOutput>> {
+        CpalAudioOutput::try_open(spec, duration, volume, self.device.as_ref())
+    }
+
+    fn set_device(&mut self, device: Option<DeviceId>) {
+        self.device = device;
+    }
 }
 
 pub struct CpalAudioOutput;
@@ -32,18 +132,13 @@ impl AudioOutputSample for i16 {}
 impl AudioOutputSample for u16 {}
 
 impl CpalAudioOutput {
-    pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-        // Get default host.
-        let host = cpal::default_host();
-
-        // Get the default audio output device.
-        let device = match host.default_output_device() {
-            Some(device) => device,
-            _ => {
-                // println!("failed to get default audio output device");
-                return Err(AudioOutputError::OpenStreamError);
-            }
-        };
+    pub fn try_open(
+        spec: SignalSpec,
+        duration: Duration,
+        volume: f32,
+        device: Option<&DeviceId>,
+    ) -> Result<Box<dyn AudioOutput>> {
+        let device = find_device(device)?;
 
         let config = match device.default_output_config() {
             Ok(config) => config,
@@ -56,13 +151,13 @@ impl CpalAudioOutput {
         // Select proper playback routine based on sample format.
         match config.sample_format() {
             cpal::SampleFormat::F32 => {
-                CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<f32>::try_open(spec, duration, volume, &device)
             }
             cpal::SampleFormat::I16 => {
-                CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<i16>::try_open(spec, duration, volume, &device)
             }
             cpal::SampleFormat::U16 => {
-                CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<u16>::try_open(spec, duration, volume, &device)
             }
             _ => Err(AudioOutputError::OpenStreamError),
         }
@@ -73,16 +168,23 @@ struct CpalAudioOutputImpl<T: AudioOutputSample>
 where
     T: AudioOutputSample,
 {
+    ring_buf: SpscRb<T>,
     ring_buf_producer: rb::Producer<T>,
     sample_buf: SampleBuffer<T>,
     stream: cpal::Stream,
     resampler: Option<Resampler<T>>,
+    gain: SharedGain,
+    /// Error reported by the output stream.
+    failed: Arc<OnceLock<String>>,
+    /// Set by the output callback when the ring buffer ran empty during playback.
+    underrun: Arc<AtomicBool>,
 }
 
 impl<T: AudioOutputSample + cpal::SizedSample> CpalAudioOutputImpl<T> {
     pub fn try_open(
         spec: SignalSpec,
         duration: Duration,
+        volume: f32,
         device: &cpal::Device,
     ) -> Result<Box<dyn AudioOutput>> {
         let num_channels = spec.channels.count();
@@ -108,17 +210,48 @@ impl<T: AudioOutputSample + cpal::SizedSample> CpalAudioOutputImpl<T> {
         let ring_buf = SpscRb::new(ring_len);
         let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
 
+        let failed = Arc::new(OnceLock::new());
+        let callback_failed = failed.clone();
+
+        let underrun = Arc::new(AtomicBool::new(false));
+        let callback_underrun = underrun.clone();
+        // Ring buffer is empty before the first write, that's not an underrun.
+        let mut has_audio = false;
+
+        let gain = SharedGain::new(volume);
+        let callback_gain = gain.clone();
+        let mut ramp = GainRamp::new(volume, config.sample_rate.0);
+        let channels = config.channels as usize;
+
         let stream_result = device.build_output_stream(
             &config,
             move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
                 // Write out as many samples as possible from the ring buffer to the audio
                 // output.
                 let written = ring_buf_consumer.read(data).unwrap_or(0);
+                has_audio |= written > 0;
+                if has_audio && written < data.len() {
+                    callback_underrun.store(true, Ordering::Relaxed);
+                    has_audio = false;
+                }
+
+                let target = callback_gain.get();
+                for frame in data[..written].chunks_mut(channels) {
+                    let g = ramp.next(target);
+                    if g != 1.0 {
+                        for s in frame.iter_mut() {
+                            *s = FromSample::from_sample(IntoSample::<f32>::into_sample(*s) * g);
+                        }
+                    }
+                }
 
                 // Mute any remaining samples.
                 data[written..].iter_mut().for_each(|s| *s = T::MID);
             },
-            move |_err| {}, // println!("audio output error: {}", err),
+            move |err| {
+                // Reported by the next write, e.g. when the device was disconnected.
+                let _ = callback_failed.set(err.to_string());
+            },
             None,
         );
 
@@ -151,10 +284,14 @@ impl<T: AudioOutputSample + cpal::SizedSample> CpalAudioOutputImpl<T> {
         };
 
         Ok(Box::new(CpalAudioOutputImpl {
+            ring_buf,
             ring_buf_producer,
             sample_buf,
             stream,
             resampler,
+            gain,
+            failed,
+            underrun,
         }))
     }
 }
@@ -166,7 +303,11 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
             return Ok(());
         }
 
-        let mut samples = if let Some(resampler) = &mut self.resampler {
+        if let Some(err) = self.failed.get() {
+            return Err(AudioOutputError::StreamClosedError(err.clone()));
+        }
+
+        let samples = if let Some(resampler) = &mut self.resampler {
             // Resampling is required. The resampler will return interleaved samples in the
             // correct sample format.
             match resampler.resample(decoded) {
@@ -181,25 +322,60 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
         };
 
         // Write all samples to the ring buffer.
-        while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
-            samples = &samples[written..];
-        }
-
-        Ok(())
+        write_samples(&self.ring_buf_producer, samples, &self.failed)
     }
 
     fn flush(&mut self) {
+        // A failed stream (e.g. its device was unplugged) plays nothing out, don't wait for it.
+        if self.failed.get().is_some() {
+            let _ = self.stream.pause();
+            return;
+        }
+
         // If there is a resampler, then it may need to be flushed
         // depending on the number of samples it has.
         if let Some(resampler) = &mut self.resampler {
-            let mut remaining_samples = resampler.flush().unwrap_or_default();
+            let remaining_samples = resampler.flush().unwrap_or_default();
+            let _ = write_samples(&self.ring_buf_producer, remaining_samples, &self.failed);
+        }
 
-            while let Some(written) = self.ring_buf_producer.write_blocking(remaining_samples) {
-                remaining_samples = &remaining_samples[written..];
-            }
+        // Wait until the output stream has played out the ring buffer. The ring buffer holds
+        // at most 200ms of audio, the timeout only guards against a stalled stream.
+        let deadline = Instant::now() + std::time::Duration::from_secs(1);
+        while !self.ring_buf.is_empty() && Instant::now() < deadline && self.failed.get().is_none() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
 
         // Flush is best-effort, ignore the returned result.
         let _ = self.stream.pause();
     }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.gain.set(volume);
+    }
+
+    fn take_underrun(&mut self) -> bool {
+        self.underrun.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Write all `samples` to the ring buffer, waiting for free space. Unlike `write_blocking`,
+/// gives up if the output stream failed and stopped consuming samples.
+fn write_samples<T: Copy + Default>(
+    producer: &rb::Producer<T>,
+    mut samples: &[T],
+    failed: &OnceLock<String>,
+) -> Result<()> {
+    while !samples.is_empty() {
+        match producer.write(samples) {
+            Ok(written) => samples = &samples[written..],
+            Err(_) => {
+                if let Some(err) = failed.get() {
+                    return Err(AudioOutputError::StreamClosedError(err.clone()));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,617 @@
+//! MPEG-DASH manifests (MPD), audio only.
+//!
+//! Of every period the first audio adaptation set is played, with its highest bandwidth
+//! representation of a codec symphonia decodes. Segments are addressed with
+//! `SegmentTemplate` (numbers or timeline), `SegmentList` or `SegmentBase` (segment index of
+//! a single file) and played by `SegmentReader`. Static manifests are VOD and seekable,
+//! dynamic ones are live and reloaded while playing.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use roxmltree::{Document, Node};
+use url::Url;
+
+use crate::http::SourceOptions;
+use crate::player_engine::{extension, StatusLink};
+use crate::segments::{manifest_url, ByteRange, Fetcher, InitSection, Manifest, Segment, SegmentList, SegmentReader};
+use crate::PlayerError;
+
+/// Segments of a dynamic manifest with a number based template kept behind the live edge,
+/// if the manifest doesn't set `timeShiftBufferDepth`.
+const DEFAULT_LIVE_WINDOW_SEGMENTS: u64 = 10;
+
+/// Whether `src` is a DASH manifest.
+pub(crate) fn is_dash(src: &str) -> bool {
+    extension(src).is_some_and(|ext| ext.eq_ignore_ascii_case("mpd"))
+}
+
+/// Fetch the manifest at `src` and open the segments of its audio.
+pub(crate) fn open(src: &str, link: Option<StatusLink>, options: &SourceOptions) -> Result<SegmentReader, PlayerError> {
+    let fetcher = Fetcher::new(options, link)?;
+    let manifest = DashManifest { url: manifest_url(src)? };
+    let list = manifest.reload(&fetcher)?;
+    SegmentReader::open(fetcher, Arc::new(manifest), list)
+}
+
+struct DashManifest {
+    url: Url,
+}
+
+impl Manifest for DashManifest {
+    fn reload(&self, fetcher: &Fetcher) -> Result<SegmentList, PlayerError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64());
+        parse_mpd(&fetcher.fetch_text(self.url.as_str())?, &self.url, now, fetcher)
+    }
+}
+
+fn invalid(message: impl Into<String>) -> PlayerError {
+    PlayerError::UnsupportedFormat(format!("DASH: {}", message.into()))
+}
+
+/// Segments of the audio of all periods. `now` (seconds since the epoch) places the live
+/// edge of dynamic manifests, `fetcher` reads segment indexes of `SegmentBase`.
+fn parse_mpd(text: &str, url: &Url, now: f64, fetcher: &Fetcher) -> Result<SegmentList, PlayerError> {
+    let doc = Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let mpd = doc.root_element();
+    if !mpd.has_tag_name("MPD") {
+        return Err(invalid("not an MPD"));
+    }
+    let dynamic = mpd.attribute("type") == Some("dynamic");
+    let total = mpd.attribute("mediaPresentationDuration").and_then(parse_duration);
+    let live = Live {
+        now: mpd.attribute("availabilityStartTime").and_then(parse_datetime).map(|start| now - start),
+        window: mpd.attribute("timeShiftBufferDepth").and_then(parse_duration),
+    };
+    let base = base_url(mpd, url);
+
+    let periods: Vec<Node> = children(mpd, "Period").collect();
+    let mut list = SegmentList {
+        ended: !dynamic,
+        ..Default::default()
+    };
+    let mut period_start = 0.0;
+    for (i, period) in periods.iter().enumerate() {
+        period_start = period.attribute("start").and_then(parse_duration).unwrap_or(period_start);
+        let period_end = period
+            .attribute("duration")
+            .and_then(parse_duration)
+            .map(|d| period_start + d)
+            .or_else(|| periods.get(i + 1)?.attribute("start").and_then(parse_duration))
+            .or(total);
+        let start = period_start;
+        period_start = period_end.unwrap_or(period_start);
+        let Some(adaptation) = children(*period, "AdaptationSet").find(|a| is_audio(*a)) else {
+            continue;
+        };
+        let Some(representation) = select_representation(adaptation) else {
+            continue;
+        };
+        let levels = [*period, adaptation, representation];
+        let base = levels.iter().fold(base.clone(), |base, level| base_url(*level, &base));
+        let period = PeriodTiming {
+            start,
+            duration: period_end.map(|end| end - start),
+        };
+
+        let first_sequence = list.segments.last().map_or(0, |s| s.sequence + 1);
+        let segments = if !inherited(&levels, "SegmentTemplate").is_empty() {
+            template_segments(&levels, &base, &period, dynamic.then_some(&live))?
+        } else if !inherited(&levels, "SegmentList").is_empty() {
+            list_segments(&levels, &base)?
+        } else {
+            base_segments(&levels, &base, fetcher)?
+        };
+        // Sequence numbers continue across periods.
+        let shift = segments.first().map_or(0, |s| first_sequence.saturating_sub(s.sequence));
+        list.segments.extend(segments.into_iter().map(|mut s| {
+            s.sequence += shift;
+            s
+        }));
+    }
+
+    list.target_duration = list.segments.iter().map(|s| s.duration).fold(0.0, f64::max);
+    Ok(list)
+}
+
+struct PeriodTiming {
+    start: f64,
+    duration: Option<f64>,
+}
+
+/// Time since `availabilityStartTime` and time shift window of a dynamic manifest.
+struct Live {
+    now: Option<f64>,
+    window: Option<f64>,
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// `base` resolved with the `BaseURL` of `node`, if any.
+fn base_url(node: Node, base: &Url) -> Url {
+    child(node, "BaseURL")
+        .and_then(|b| b.text())
+        .and_then(|text| base.join(text.trim()).ok())
+        .unwrap_or_else(|| base.clone())
+}
+
+fn is_audio(adaptation: Node) -> bool {
+    let audio_mime = |n: Node| n.attribute("mimeType").is_some_and(|m| m.starts_with("audio/"));
+    adaptation.attribute("contentType") == Some("audio")
+        || audio_mime(adaptation)
+        || children(adaptation, "Representation").any(audio_mime)
+        || children(adaptation, "ContentComponent").any(|c| c.attribute("contentType") == Some("audio"))
+}
+
+/// Highest bandwidth representation with a codec symphonia decodes, otherwise the highest
+/// bandwidth one.
+fn select_representation<'a, 'input>(adaptation: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+    const DECODABLE: [&str; 4] = ["mp4a", "flac", "mp3", "alac"];
+    let mut representations: Vec<Node> = children(adaptation, "Representation").collect();
+    representations.sort_by_key(|r| std::cmp::Reverse(r.attribute("bandwidth").and_then(|b| b.parse::<u64>().ok())));
+    let decodable = |r: &&Node| {
+        r.attribute("codecs")
+            .or(adaptation.attribute("codecs"))
+            .is_some_and(|codecs| DECODABLE.iter().any(|c| codecs.starts_with(c)))
+    };
+    representations.iter().find(decodable).or(representations.first()).copied()
+}
+
+/// Elements `name` of the levels (period, adaptation set, representation), innermost first.
+fn inherited<'a, 'input>(levels: &[Node<'a, 'input>], name: &'static str) -> Vec<Node<'a, 'input>> {
+    levels.iter().rev().filter_map(|level| child(*level, name)).collect()
+}
+
+/// Attribute of the innermost element that has it.
+fn attr<'a>(nodes: &[Node<'a, '_>], name: &str) -> Option<&'a str> {
+    nodes.iter().find_map(|n| n.attribute(name))
+}
+
+/// Child element of the innermost element that has it.
+fn child_of<'a, 'input>(nodes: &[Node<'a, 'input>], name: &'static str) -> Option<Node<'a, 'input>> {
+    nodes.iter().find_map(|n| child(*n, name))
+}
+
+fn number<T: std::str::FromStr>(nodes: &[Node], name: &str) -> Option<T> {
+    attr(nodes, name).and_then(|v| v.trim().parse().ok())
+}
+
+/// `(start, duration)` of the segments of a `SegmentTimeline`, in timescale units. Entries
+/// repeated until the next one (`r="-1"`) end at `end`.
+fn timeline(timeline: Node, end: Option<u64>) -> Vec<(u64, u64)> {
+    let entries: Vec<Node> = children(timeline, "S").collect();
+    let mut segments = Vec::new();
+    let mut time = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let parse = |name| entry.attribute(name).and_then(|v: &str| v.parse::<i64>().ok());
+        time = parse("t").map_or(time, |t| t.max(0) as u64);
+        let Some(duration) = parse("d").filter(|&d| d > 0).map(|d| d as u64) else {
+            continue;
+        };
+        let repeat = match parse("r").unwrap_or(0) {
+            r if r >= 0 => r as u64,
+            _ => {
+                let until = entries.get(i + 1).and_then(|next| next.attribute("t")?.parse::<u64>().ok()).or(end);
+                until.map_or(0, |until| until.saturating_sub(time).div_ceil(duration).saturating_sub(1))
+            }
+        };
+        for _ in 0..=repeat {
+            segments.push((time, duration));
+            time += duration;
+        }
+    }
+    segments
+}
+
+fn template_segments(
+    levels: &[Node],
+    base: &Url,
+    period: &PeriodTiming,
+    live: Option<&Live>,
+) -> Result<Vec<Segment>, PlayerError> {
+    let templates = inherited(levels, "SegmentTemplate");
+    let representation = levels[levels.len() - 1];
+    let id = representation.attribute("id").unwrap_or_default();
+    let bandwidth = representation.attribute("bandwidth").unwrap_or_default();
+    let timescale = number::<u64>(&templates, "timescale").unwrap_or(1).max(1);
+    let offset = number::<u64>(&templates, "presentationTimeOffset").unwrap_or(0);
+    let start_number = number::<u64>(&templates, "startNumber").unwrap_or(1);
+    let media = attr(&templates, "media").ok_or_else(|| invalid("SegmentTemplate without media"))?;
+    let by_time = media.contains("$Time$");
+    let resolve = |template: &str, number: u64, time: u64| {
+        base.join(&fill_template(template, id, bandwidth, number, time))
+            .map(String::from)
+            .map_err(|e| invalid(e.to_string()))
+    };
+    let map = attr(&templates, "initialization")
+        .map(|init| resolve(init, 0, 0))
+        .transpose()?
+        .map(|uri| InitSection { uri, range: None });
+
+    // (number, time) of the segments, in timescale units.
+    let mut entries = Vec::new();
+    if let Some(timeline_node) = child_of(&templates, "SegmentTimeline") {
+        let end = period.duration.map(|d| offset + (d * timescale as f64) as u64);
+        // Live timelines repeating until the next entry end at the live edge.
+        let end = end.or_else(|| {
+            let now = live?.now? - period.start;
+            Some(offset + (now.max(0.0) * timescale as f64) as u64)
+        });
+        for (i, (time, duration)) in timeline(timeline_node, end).into_iter().enumerate() {
+            entries.push((start_number + i as u64, time, duration));
+        }
+    } else {
+        let duration = number::<u64>(&templates, "duration").ok_or_else(|| invalid("SegmentTemplate without duration"))?;
+        let seg_duration = duration as f64 / timescale as f64;
+        let range = match (live, period.duration) {
+            (Some(live), _) => {
+                // Complete segments since the period start, within the time shift window.
+                let elapsed = live.now.unwrap_or(0.0) - period.start;
+                let available = (elapsed / seg_duration).floor().max(0.0) as u64;
+                let window = live
+                    .window
+                    .map_or(DEFAULT_LIVE_WINDOW_SEGMENTS, |w| (w / seg_duration).ceil() as u64);
+                available.saturating_sub(window)..available
+            }
+            (None, Some(period_duration)) => 0..(period_duration / seg_duration).ceil() as u64,
+            (None, None) => return Err(invalid("SegmentTemplate without timeline or period duration")),
+        };
+        for i in range {
+            entries.push((start_number + i, offset + i * duration, duration));
+        }
+    }
+
+    entries
+        .into_iter()
+        .map(|(number, time, duration)| {
+            Ok(Segment {
+                sequence: if by_time { time } else { number },
+                uri: resolve(media, number, time)?,
+                duration: duration as f64 / timescale as f64,
+                range: None,
+                map: map.clone(),
+                encrypted: false,
+            })
+        })
+        .collect()
+}
+
+fn list_segments(levels: &[Node], base: &Url) -> Result<Vec<Segment>, PlayerError> {
+    let lists = inherited(levels, "SegmentList");
+    let timescale = number::<u64>(&lists, "timescale").unwrap_or(1).max(1);
+    let start_number = number::<u64>(&lists, "startNumber").unwrap_or(1);
+    let resolve = |uri: Option<&str>| match uri {
+        Some(uri) => base.join(uri).map(String::from).map_err(|e| invalid(e.to_string())),
+        None => Ok(base.to_string()),
+    };
+    let map = child_of(&lists, "Initialization")
+        .map(|init| {
+            Ok::<_, PlayerError>(InitSection {
+                uri: resolve(init.attribute("sourceURL"))?,
+                range: init.attribute("range").and_then(parse_range),
+            })
+        })
+        .transpose()?;
+    let durations: Vec<u64> = match child_of(&lists, "SegmentTimeline") {
+        Some(timeline_node) => timeline(timeline_node, None).into_iter().map(|(_, d)| d).collect(),
+        None => Vec::new(),
+    };
+    let duration = number::<u64>(&lists, "duration").unwrap_or(0);
+
+    // Segment urls are not inherited.
+    children(lists[0], "SegmentURL")
+        .enumerate()
+        .map(|(i, url)| {
+            Ok(Segment {
+                sequence: start_number + i as u64,
+                uri: resolve(url.attribute("media"))?,
+                duration: durations.get(i).copied().unwrap_or(duration) as f64 / timescale as f64,
+                range: url.attribute("mediaRange").and_then(parse_range),
+                map: map.clone(),
+                encrypted: false,
+            })
+        })
+        .collect()
+}
+
+/// Segments of a single file: ranges from its segment index (`sidx`), or the whole file.
+fn base_segments(levels: &[Node], base: &Url, fetcher: &Fetcher) -> Result<Vec<Segment>, PlayerError> {
+    let bases = inherited(levels, "SegmentBase");
+    let uri = base.to_string();
+    let whole_file = |duration| {
+        vec![Segment {
+            sequence: 0,
+            uri: uri.clone(),
+            duration,
+            range: None,
+            map: None,
+            encrypted: false,
+        }]
+    };
+    let Some(index_range) = attr(&bases, "indexRange").and_then(parse_range) else {
+        return Ok(whole_file(0.0));
+    };
+
+    let index = fetcher.fetch(&uri, Some(&index_range))?;
+    let index = parse_sidx(&index).ok_or_else(|| invalid("invalid segment index"))?;
+    let init = child_of(&bases, "Initialization")
+        .and_then(|init| init.attribute("range"))
+        .and_then(parse_range)
+        .unwrap_or(ByteRange {
+            start: 0,
+            len: index_range.start,
+        });
+    let mut start = index_range.start + index_range.len + index.first_offset;
+    Ok(index
+        .references
+        .into_iter()
+        .enumerate()
+        .map(|(i, (size, duration))| {
+            let segment = Segment {
+                sequence: i as u64,
+                uri: uri.clone(),
+                duration: duration as f64 / index.timescale.max(1) as f64,
+                range: Some(ByteRange { start, len: size }),
+                map: Some(InitSection {
+                    uri: uri.clone(),
+                    range: Some(init.clone()),
+                }),
+                encrypted: false,
+            };
+            start += size;
+            segment
+        })
+        .collect())
+}
+
+/// Segment index (`sidx` box).
+struct SegmentIndex {
+    timescale: u32,
+    /// Distance of the first segment from the end of the box.
+    first_offset: u64,
+    /// `(size, duration)` of the segments.
+    references: Vec<(u64, u64)>,
+}
+
+fn parse_sidx(data: &[u8]) -> Option<SegmentIndex> {
+    let u32_at = |pos: usize| Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?));
+    let u64_at = |pos: usize| Some(u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?));
+    if data.get(4..8)? != b"sidx" {
+        return None;
+    }
+    let version = *data.get(8)?;
+    let timescale = u32_at(16)?;
+    let (first_offset, mut pos) = match version {
+        0 => (u32_at(24)? as u64, 28),
+        _ => (u64_at(28)?, 36),
+    };
+    let count = u16::from_be_bytes(data.get(pos + 2..pos + 4)?.try_into().ok()?);
+    pos += 4;
+    let mut references = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let size = (u32_at(pos)? & 0x7fff_ffff) as u64;
+        references.push((size, u32_at(pos + 4)? as u64));
+        pos += 12;
+    }
+    Some(SegmentIndex {
+        timescale,
+        first_offset,
+        references,
+    })
+}
+
+/// `$RepresentationID$`, `$Number$`, `$Bandwidth$` and `$Time$` (with optional `%0<width>d`
+/// format) and `$$` substituted in `template`.
+fn fill_template(template: &str, id: &str, bandwidth: &str, number: u64, time: u64) -> String {
+    let mut out = String::new();
+    for (i, part) in template.split('$').enumerate() {
+        if i % 2 == 0 {
+            out.push_str(part);
+            continue;
+        }
+        let (name, format) = part.split_once('%').unwrap_or((part, ""));
+        let width = format.trim_start_matches('0').trim_end_matches('d').parse().unwrap_or(0);
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(id),
+            "Bandwidth" => out.push_str(&format!("{:0width$}", bandwidth, width = width)),
+            "Number" => out.push_str(&format!("{:0width$}", number, width = width)),
+            "Time" => out.push_str(&format!("{:0width$}", time, width = width)),
+            _ => {
+                out.push('$');
+                out.push_str(part);
+                out.push('$');
+            }
+        }
+    }
+    out
+}
+
+/// `<first>-<last>` byte range.
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let (first, last) = value.split_once('-')?;
+    let (first, last): (u64, u64) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+    Some(ByteRange {
+        start: first,
+        len: last.checked_sub(first)? + 1,
+    })
+}
+
+/// ISO 8601 duration (`PT1H2M3.5S`, `P1DT2H`) in seconds. Years and months are not used by
+/// manifests and not supported.
+fn parse_duration(value: &str) -> Option<f64> {
+    let value = value.trim().strip_prefix('P')?;
+    let (date, time) = value.split_once('T').unwrap_or((value, ""));
+    let mut seconds = 0.0;
+    for (part, units) in [(date, &[('D', 86400.0)][..]), (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)][..])] {
+        let mut rest = part;
+        for &(unit, factor) in units {
+            if let Some((n, next)) = rest.split_once(unit) {
+                seconds += n.parse::<f64>().ok()? * factor;
+                rest = next;
+            }
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+    }
+    Some(seconds)
+}
+
+/// `YYYY-MM-DDTHH:MM:SS[.fff][Z|±HH:MM]` in seconds since the epoch.
+pub(crate) fn parse_datetime(value: &str) -> Option<f64> {
+    let (date, time) = value.trim().split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|n| n.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(i) => {
+            let (time, zone) = time.split_at(i);
+            let offset = match zone.split_once(':') {
+                Some((h, m)) => {
+                    let sign = if zone.starts_with('-') { -1.0 } else { 1.0 };
+                    sign * (h[1..].parse::<f64>().ok()? * 3600.0 + m.parse::<f64>().ok()? * 60.0)
+                }
+                None => 0.0,
+            };
+            (time, offset)
+        }
+        None => (time, 0.0),
+    };
+    let mut time = time.splitn(3, ':').map(|n| n.parse::<f64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next().flatten().unwrap_or(0.0));
+
+    // Days since the epoch of the civil date (proleptic Gregorian calendar).
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Some(days as f64 * 86400.0 + hours * 3600.0 + minutes * 60.0 + seconds - offset)
+}
+
+#[test]
+fn parses_manifest_values() {
+    assert_eq!(parse_duration("PT1H2M3.5S"), Some(3723.5));
+    assert_eq!(parse_duration("P1DT30M"), Some(88200.0));
+    assert_eq!(parse_duration("1:00"), None);
+    assert_eq!(parse_datetime("1970-01-02T00:00:10Z"), Some(86410.0));
+    assert_eq!(parse_datetime("2024-03-01T01:00:00+01:00"), Some(1709251200.0));
+    assert_eq!(
+        fill_template("$RepresentationID$/$Number%05d$-$Time$$$.m4s", "a1", "64000", 7, 9000),
+        "a1/00007-9000$.m4s"
+    );
+    assert_eq!(parse_range("100-199"), Some(ByteRange { start: 100, len: 100 }));
+}
+
+#[test]
+fn parses_segment_addressing() {
+    let url = Url::parse("https://cdn.example.com/show/manifest.mpd").unwrap();
+    let fetcher = Fetcher::new(&SourceOptions::default(), None).unwrap();
+
+    let timeline = r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+  <Period>
+    <AdaptationSet contentType="video"><Representation id="v" bandwidth="900000"/></AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4">
+      <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Time$.m4s">
+        <SegmentTimeline><S t="0" d="4000" r="1"/><S d="2000"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="opus" codecs="opus" bandwidth="160000"/>
+      <Representation id="aac" codecs="mp4a.40.2" bandwidth="128000"/>
+      <Representation id="aac-low" codecs="mp4a.40.5" bandwidth="48000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+    let list = parse_mpd(timeline, &url, 0.0, &fetcher).unwrap();
+    assert!(list.ended);
+    assert_eq!(list.duration(), 10.0);
+    assert_eq!(list.target_duration, 4.0);
+    let sequences: Vec<u64> = list.segments.iter().map(|s| s.sequence).collect();
+    assert_eq!(sequences, [0, 4000, 8000]);
+    assert_eq!(list.segments[2].uri, "https://cdn.example.com/show/aac/8000.m4s");
+    assert_eq!(list.segments[0].map.as_ref().unwrap().uri, "https://cdn.example.com/show/aac/init.mp4");
+
+    // Live: ten second segments, 95 seconds after the availability start.
+    let live = r#"<MPD type="dynamic" availabilityStartTime="1970-01-01T00:00:00Z" timeShiftBufferDepth="PT30S">
+  <Period start="PT0S">
+    <AdaptationSet contentType="audio">
+      <SegmentTemplate duration="10" startNumber="1" media="live/$Number$.m4s"/>
+      <Representation id="a" codecs="mp4a.40.2" bandwidth="64000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+    let list = parse_mpd(live, &url, 95.0, &fetcher).unwrap();
+    assert!(!list.ended);
+    let sequences: Vec<u64> = list.segments.iter().map(|s| s.sequence).collect();
+    assert_eq!(sequences, [7, 8, 9]);
+    assert_eq!(list.segments[2].uri, "https://cdn.example.com/show/live/9.m4s");
+
+    let segment_list = r#"<MPD type="static" mediaPresentationDuration="PT6S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet contentType="audio">
+      <Representation id="a" codecs="mp4a.40.2" bandwidth="64000">
+        <SegmentList timescale="10" duration="30">
+          <Initialization sourceURL="audio.mp4" range="0-699"/>
+          <SegmentURL media="audio.mp4" mediaRange="700-1699"/>
+          <SegmentURL media="audio.mp4" mediaRange="1700-2499"/>
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+    let list = parse_mpd(segment_list, &url, 0.0, &fetcher).unwrap();
+    assert_eq!(list.duration(), 6.0);
+    assert_eq!(list.segments[1].uri, "https://cdn.example.com/show/media/audio.mp4");
+    assert_eq!(list.segments[1].range, Some(ByteRange { start: 1700, len: 800 }));
+    assert_eq!(list.segments[1].map.as_ref().unwrap().range, Some(ByteRange { start: 0, len: 700 }));
+
+    assert!(parse_mpd("<html/>", &url, 0.0, &fetcher).is_err());
+}
+
+#[test]
+fn decodes_and_seeks_segments() {
+    use crate::test_util::{http_response, http_server, wav_bytes};
+    use crate::Decoder;
+
+    // One second segments of PCM after a WAV header as initialization segment.
+    let wav = wav_bytes(8000, 1, 32000, |i| (i % 1000) as i16);
+    let (init, pcm) = wav.split_at(44);
+    let (init, pcm) = (init.to_vec(), pcm.to_vec());
+    let manifest = r#"<MPD type="static" mediaPresentationDuration="PT4S">
+  <Period>
+    <AdaptationSet mimeType="audio/wav">
+      <SegmentTemplate duration="1" initialization="init.wav" media="seg$Number$.pcm" startNumber="0"/>
+      <Representation id="pcm" bandwidth="128000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    let url = http_server(move |req| {
+        let body = match req.path.as_str() {
+            "/radio/manifest.mpd" => manifest.as_bytes().to_vec(),
+            "/radio/init.wav" => init.clone(),
+            path => match path.strip_prefix("/radio/seg").and_then(|p| p.strip_suffix(".pcm")) {
+                Some(i) => pcm.chunks(16000).nth(i.parse().unwrap()).unwrap().to_vec(),
+                None => return http_response(404, &[], b""),
+            },
+        };
+        http_response(200, &[("content-length", body.len().to_string())], &body)
+    });
+
+    let mut decoder = Decoder::open(&format!("{}/radio/manifest.mpd", url)).unwrap();
+    assert_eq!(decoder.duration(), Some(4.0));
+    let samples: Vec<f32> = decoder.by_ref().flat_map(|b| b.unwrap().samples).collect();
+    assert_eq!(samples.len(), 32000);
+
+    assert_eq!(decoder.seek(3.2).unwrap(), 3.0);
+    let block = decoder.next().unwrap().unwrap();
+    assert_eq!(block.timestamp, 3.0);
+    assert_eq!(block.samples[..2], [0.0, 1.0 / 32768.0]);
+}
@@ -0,0 +1,430 @@
+//! Decoding to PCM without playback.
+//!
+//! `Decoder` opens sources the same way as `Player` (local files, `file://` and `http(s)://`
+//! urls with range requests) and yields decoded audio as interleaved `f32` samples, e.g. for
+//! analysis. No audio device is opened.
+
+use std::sync::Arc;
+
+use symphonia::core::audio::{AsAudioBufferRef, SampleBuffer, SignalSpec};
+use symphonia::core::formats::{SeekMode, SeekTo};
+use symphonia::core::units::Time;
+
+use crate::player_engine::{load_track, skip_frames, skip_start, ts_seconds, update_metadata, LoadedTrack};
+use crate::chapters::Chapter;
+use crate::cache::DiskCache;
+use crate::http::{HttpConfig, HttpTransport, ReadAhead, ReconnectPolicy, SourceOptions};
+use crate::metadata::Metadata;
+use crate::resampler::Resampler;
+use crate::PlayerError;
+
+/// Block of decoded audio.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedAudio {
+    /// Spec of the samples. The rate is the target rate when resampling.
+    pub spec: SignalSpec,
+    /// Position of the first frame in seconds.
+    pub timestamp: f64,
+    /// Interleaved samples.
+    pub samples: Vec<f32>,
+}
+
+impl DecodedAudio {
+    /// Number of frames (samples per channel).
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.spec.channels.count().max(1)
+    }
+}
+
+/// Pull decoder yielding `DecodedAudio` blocks until the end of stream.
+///
+/// ```no_run
+/// use url2audio::Decoder;
+///
+/// let decoder = Decoder::open("/tmp/test.mp3").unwrap().resample_to(16000);
+/// for block in decoder {
+///     let block = block.unwrap();
+///     println!("{:.2}s: {} frames", block.timestamp, block.frames());
+/// }
+/// ```
+pub struct Decoder {
+    track: LoadedTrack,
+    sample_buf: Option<SampleBuffer<f32>>,
+    target_rate: Option<u32>,
+    resampler: Option<ResampleState>,
+    seek_mode: SeekMode,
+    /// After an accurate seek, timestamp up to which decoded audio is discarded.
+    skip_until: Option<u64>,
+    finished: bool,
+}
+
+/// Resampler for one signal spec with the frame counts needed for timestamps.
+struct ResampleState {
+    resampler: Resampler<f32>,
+    spec: SignalSpec,
+    /// Position of the first resampled frame.
+    start: f64,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl Decoder {
+    /// Open a local path, `file://` or `http(s)://` url and probe its format.
+    pub fn open(src: &str) -> Result<Self, PlayerError> {
+        Decoder::builder().open(src)
+    }
+
+    /// Builder for opening HTTP sources with non-default options.
+    pub fn builder() -> DecoderBuilder {
+        DecoderBuilder::default()
+    }
+
+    /// Resample decoded audio to `rate` Hz.
+    pub fn resample_to(mut self, rate: u32) -> Self {
+        self.target_rate = Some(rate);
+        self
+    }
+
+    /// Tags and cover art read so far.
+    pub fn metadata(&self) -> &Metadata {
+        &self.track.metadata
+    }
+
+    /// Chapters of the track, ordered by start.
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.track.chapters
+    }
+
+    /// Seek mode used by `seek`. Default is `SeekMode::Coarse`.
+    pub fn seek_mode(mut self, mode: SeekMode) -> Self {
+        self.seek_mode = mode;
+        self
+    }
+
+    /// Duration in seconds, if known.
+    pub fn duration(&self) -> Option<f64> {
+        ts_seconds(self.track.n_frames?, self.track.time_base)
+    }
+
+    /// Seek to `seconds`. Returns the position decoding continues from, which may be
+    /// before the requested one with `SeekMode::Coarse`.
+    pub fn seek(&mut self, seconds: f64) -> Result<f64, PlayerError> {
+        let seek_to = SeekTo::Time {
+            time: Time::from(seconds),
+            track_id: Some(self.track.track_id),
+        };
+        let seeked = self
+            .track
+            .reader
+            .seek(self.seek_mode, seek_to)
+            .map_err(|e| PlayerError::SeekFailed(e.to_string()))?;
+        self.track.decoder.reset();
+        self.resampler = None;
+        self.finished = false;
+        let landed_ts = match self.seek_mode {
+            SeekMode::Accurate => seeked.required_ts.max(seeked.actual_ts),
+            SeekMode::Coarse => seeked.actual_ts,
+        };
+        self.skip_until = (self.seek_mode == SeekMode::Accurate).then_some(landed_ts);
+        Ok(ts_seconds(landed_ts, self.track.time_base).unwrap_or(seconds))
+    }
+
+    /// Decode the next packet. `Ok(None)` at the end of stream.
+    fn next_block(&mut self) -> Result<Option<DecodedAudio>, PlayerError> {
+        loop {
+            let packet = match self.track.reader.next_packet() {
+                Ok(packet) => packet,
+                Err(symphonia::core::errors::Error::IoError(e))
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(self.flush_resampler());
+                }
+                Err(e) => return Err(e.into()),
+            };
+            update_metadata(self.track.reader.as_mut(), &mut self.track.metadata);
+            if packet.track_id() != self.track.track_id {
+                continue;
+            }
+
+            let mut ts = packet.ts();
+            let decoded = self.track.decoder.decode(&packet)?;
+            let trimmed = match self.skip_until {
+                Some(skip_until) if ts + packet.dur() <= skip_until => continue,
+                Some(skip_until) => {
+                    self.skip_until = None;
+                    let skip = skip_frames(skip_until.saturating_sub(ts), self.track.time_base, decoded.spec().rate);
+                    ts = ts.max(skip_until);
+                    (skip > 0).then(|| skip_start(&decoded, skip))
+                }
+                None => None,
+            };
+            let decoded = match &trimmed {
+                Some(buf) => buf.as_audio_buffer_ref(),
+                None => decoded,
+            };
+            if decoded.frames() == 0 {
+                continue;
+            }
+            let timestamp = ts_seconds(ts, self.track.time_base).unwrap_or(0.0);
+            let spec = *decoded.spec();
+            let capacity = decoded.capacity() as u64;
+
+            let Some(rate) = self.target_rate.filter(|&rate| rate != spec.rate) else {
+                let sample_buf = match self.sample_buf.as_mut() {
+                    Some(buf) if buf.capacity() >= decoded.capacity() * spec.channels.count() => buf,
+                    _ => self.sample_buf.insert(SampleBuffer::new(capacity, spec)),
+                };
+                sample_buf.copy_interleaved_ref(decoded);
+                return Ok(Some(DecodedAudio {
+                    spec,
+                    timestamp,
+                    samples: sample_buf.samples().to_vec(),
+                }));
+            };
+
+            if self.resampler.as_ref().is_some_and(|r| r.spec != spec) {
+                self.resampler = None;
+            }
+            let state = self.resampler.get_or_insert_with(|| ResampleState {
+                resampler: Resampler::new(spec, rate as usize, capacity),
+                spec,
+                start: timestamp,
+                frames_in: 0,
+                frames_out: 0,
+            });
+            state.frames_in += decoded.frames() as u64;
+            if let Some(block) = state.resampled(rate, |r| r.resample(decoded)) {
+                return Ok(Some(block));
+            }
+        }
+    }
+
+    /// Remaining audio of the resampler without the silence it pads its last block with.
+    fn flush_resampler(&mut self) -> Option<DecodedAudio> {
+        let rate = self.target_rate?;
+        let mut state = self.resampler.take()?;
+        let expected = state.frames_in * rate as u64 / state.spec.rate as u64;
+        let remaining = expected.saturating_sub(state.frames_out) as usize;
+        let mut block = state.resampled(rate, |r| r.flush())?;
+        block.samples.truncate(remaining * state.spec.channels.count());
+        (!block.samples.is_empty()).then_some(block)
+    }
+}
+
+impl ResampleState {
+    fn resampled(
+        &mut self,
+        rate: u32,
+        f: impl FnOnce(&mut Resampler<f32>) -> Option<&[f32]>,
+    ) -> Option<DecodedAudio> {
+        let samples = f(&mut self.resampler)?.to_vec();
+        let spec = SignalSpec::new(rate, self.spec.channels);
+        let timestamp = self.start + self.frames_out as f64 / rate as f64;
+        self.frames_out += (samples.len() / self.spec.channels.count()) as u64;
+        Some(DecodedAudio {
+            spec,
+            timestamp,
+            samples,
+        })
+    }
+}
+
+/// Options of the HTTP requests of a `Decoder`, same as those of `PlayerBuilder`.
+///
+/// ```no_run
+/// use url2audio::{Decoder, HttpConfig};
+///
+/// let decoder = Decoder::builder()
+///     .http(HttpConfig::new().bearer_auth("token"))
+///     .open("https://example.com/episode.mp3")
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct DecoderBuilder {
+    source_options: SourceOptions,
+}
+
+impl DecoderBuilder {
+    /// Reconnect of HTTP sources after network failures. Default is
+    /// `ReconnectPolicy::default()`.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.source_options.reconnect = policy;
+        self
+    }
+
+    /// How much of HTTP sources is downloaded in background ahead of the read position.
+    /// Default is `ReadAhead::Seconds(30.0)`.
+    pub fn read_ahead(mut self, read_ahead: ReadAhead) -> Self {
+        self.source_options.read_ahead = read_ahead;
+        self
+    }
+
+    /// Headers, user agent, authorization, timeouts, proxy and redirect limit of the requests
+    /// of HTTP sources.
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.source_options.http = http;
+        self
+    }
+
+    /// Make the requests of HTTP sources with `transport` instead of ureq.
+    pub fn transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.source_options.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Read and store downloaded chunks of HTTP sources in `cache`.
+    pub fn cache(mut self, cache: DiskCache) -> Self {
+        self.source_options.cache = Some(cache);
+        self
+    }
+
+    /// Open `src` like `Decoder::open` with the options set.
+    pub fn open(self, src: &str) -> Result<Decoder, PlayerError> {
+        Ok(Decoder {
+            track: load_track(src, None, &self.source_options)?,
+            sample_buf: None,
+            target_rate: None,
+            resampler: None,
+            seek_mode: SeekMode::Coarse,
+            skip_until: None,
+            finished: false,
+        })
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Result<DecodedAudio, PlayerError>;
+
+    /// Next block of audio. After an error other than a decode error of a single packet
+    /// the iterator ends.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_block() {
+            Ok(Some(block)) => Some(Ok(block)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = !matches!(e, PlayerError::Decode(_));
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[test]
+fn decodes_and_seeks_local_file() {
+    use crate::test_util::{temp_path, write_wav};
+
+    let path = temp_path("decode.wav");
+    write_wav(&path, 8000, 2, 16000, |i| (i / 8) as i16);
+
+    let mut decoder = Decoder::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(decoder.duration(), Some(2.0));
+
+    let blocks: Vec<_> = decoder.by_ref().map(|b| b.unwrap()).collect();
+    assert_eq!(blocks.iter().map(|b| b.frames()).sum::<usize>(), 16000);
+    assert_eq!(blocks[0].timestamp, 0.0);
+    assert_eq!(blocks[0].spec.channels.count(), 2);
+    assert_eq!(blocks[0].samples[..2], [0.0, 0.0]);
+
+    let position = decoder.seek(1.5).unwrap();
+    assert!(position <= 1.5);
+    let block = decoder.next().unwrap().unwrap();
+    assert_eq!(block.timestamp, position);
+    let frame = (position * 8000.0) as u32;
+    assert_eq!(block.samples[0], (frame / 8) as f32 / 32768.0);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn accurate_seek_skips_to_requested_frame() {
+    use crate::test_util::{temp_path, write_wav};
+
+    let path = temp_path("decode-accurate.wav");
+    write_wav(&path, 8000, 1, 16000, |i| i as i16);
+
+    let mut decoder = Decoder::open(path.to_str().unwrap()).unwrap().seek_mode(SeekMode::Accurate);
+    assert_eq!(decoder.seek(1.5).unwrap(), 1.5);
+    let block = decoder.next().unwrap().unwrap();
+    assert_eq!(block.timestamp, 1.5);
+    assert_eq!(block.samples[0], 12000.0 / 32768.0);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn resamples_to_target_rate() {
+    use crate::test_util::{temp_path, write_wav};
+
+    let path = temp_path("resample.wav");
+    write_wav(&path, 8000, 1, 8000, |_| 1000);
+
+    let decoder = Decoder::open(path.to_str().unwrap()).unwrap().resample_to(16000);
+    let blocks: Vec<_> = decoder.map(|b| b.unwrap()).collect();
+    assert!(blocks.iter().all(|b| b.spec.rate == 16000));
+    assert_eq!(blocks.iter().map(|b| b.frames()).sum::<usize>(), 16000);
+    let last = blocks.last().unwrap();
+    assert!((last.timestamp + last.frames() as f64 / 16000.0 - 1.0).abs() < 1e-9);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn opens_through_custom_transport() {
+    use std::sync::Mutex;
+
+    use crate::http::HttpResponse;
+    use crate::test_util::{temp_path, write_wav};
+
+    type Headers = Vec<(String, String)>;
+
+    struct MemoryTransport {
+        wav: Vec<u8>,
+        requests: Arc<Mutex<Vec<Headers>>>,
+    }
+
+    impl HttpTransport for MemoryTransport {
+        fn get(&self, url: &str, range_start: Option<u64>, headers: &[(String, String)]) -> std::io::Result<HttpResponse> {
+            assert_eq!(url, "mem://tone.wav");
+            self.requests.lock().unwrap().push(headers.to_vec());
+            let start = range_start.unwrap_or(0) as usize;
+            let len = self.wav.len();
+            let mut response_headers = vec![
+                ("Content-Length".to_string(), (len - start).to_string()),
+                ("Accept-Ranges".to_string(), "bytes".to_string()),
+            ];
+            if range_start.is_some() {
+                response_headers.push(("Content-Range".to_string(), format!("bytes {}-{}/{}", start, len - 1, len)));
+            }
+            Ok(HttpResponse {
+                status: if range_start.is_some() { 206 } else { 200 },
+                headers: response_headers,
+                body: Box::new(std::io::Cursor::new(self.wav[start..].to_vec())),
+            })
+        }
+    }
+
+    let path = temp_path("transport.wav");
+    write_wav(&path, 8000, 1, 8000, |i| i as i16);
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let transport = MemoryTransport {
+        wav: std::fs::read(&path).unwrap(),
+        requests: requests.clone(),
+    };
+    let _ = std::fs::remove_file(path);
+
+    let decoder = Decoder::builder()
+        .transport(transport)
+        .http(HttpConfig::new().header("X-Feed", "premium"))
+        .open("mem://tone.wav")
+        .unwrap();
+    assert_eq!(decoder.duration(), Some(1.0));
+    assert_eq!(decoder.map(|b| b.unwrap().frames()).sum::<usize>(), 8000);
+
+    let requests = requests.lock().unwrap();
+    assert!(!requests.is_empty());
+    let feed = ("X-Feed".to_string(), "premium".to_string());
+    assert!(requests.iter().all(|headers| headers.contains(&feed)));
+}
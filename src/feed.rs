@@ -0,0 +1,456 @@
+//! Podcast feeds: RSS 2.0 with the iTunes, Podcasting 2.0 (`podcast:`) and Podlove Simple
+//! Chapters (`psc:`) extensions, and Atom.
+//!
+//! Only items with an enclosure (the audio of the episode) are episodes, their `url` is
+//! opened with `Player::open` or `Decoder::open` like any other source.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use roxmltree::{Document, Node, ParsingOptions};
+use url::Url;
+
+use crate::dash::parse_datetime;
+use crate::http::{HttpConfig, SourceOptions};
+use crate::segments::{manifest_url, Fetcher};
+use crate::PlayerError;
+
+/// Podcast feed with its episodes in feed order (usually newest first).
+///
+/// ```no_run
+/// use url2audio::{Feed, Player};
+///
+/// let feed = Feed::fetch("https://example.com/podcast.xml").unwrap();
+/// if let Some(episode) = feed.episodes.first() {
+///     Player::new().open(&episode.url);
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Website of the podcast.
+    pub link: Option<String>,
+    pub author: Option<String>,
+    /// Cover art url.
+    pub image: Option<String>,
+    pub language: Option<String>,
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Episode {
+    pub title: Option<String>,
+    /// `guid` (RSS) or `id` (Atom).
+    pub guid: Option<String>,
+    pub description: Option<String>,
+    /// Web page of the episode.
+    pub link: Option<String>,
+    /// Enclosure url, the audio of the episode.
+    pub url: String,
+    /// Enclosure length in bytes, as announced by the feed.
+    pub length: Option<u64>,
+    /// Enclosure content type, e.g. `audio/mpeg`.
+    pub mime_type: Option<String>,
+    pub published: Option<SystemTime>,
+    /// `itunes:duration` in seconds.
+    pub duration: Option<f64>,
+    /// Episode cover art url, the feed's if the episode has none.
+    pub image: Option<String>,
+    /// Chapters listed in the feed (`psc:chapters`).
+    pub chapters: Vec<EpisodeChapter>,
+    /// Chapters file (`podcast:chapters`), usually JSON chapters.
+    pub chapters_url: Option<FeedLink>,
+    /// Transcripts (`podcast:transcript`).
+    pub transcripts: Vec<FeedLink>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpisodeChapter {
+    /// Start in seconds.
+    pub start: f64,
+    pub title: String,
+    pub url: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Link to a document belonging to an episode.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedLink {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub language: Option<String>,
+    /// `rel` of transcripts, e.g. `captions`.
+    pub rel: Option<String>,
+}
+
+impl Feed {
+    /// Fetch and parse the feed at `url` (or local path).
+    pub fn fetch(url: &str) -> Result<Feed, PlayerError> {
+        Feed::fetch_with(url, &HttpConfig::default())
+    }
+
+    /// `fetch` with custom headers, authorization or timeouts, e.g. for private feeds.
+    pub fn fetch_with(url: &str, http: &HttpConfig) -> Result<Feed, PlayerError> {
+        let options = SourceOptions {
+            http: http.clone(),
+            ..Default::default()
+        };
+        let fetcher = Fetcher::new(&options, None)?;
+        let url = manifest_url(url)?;
+        Feed::parse(&fetcher.fetch_text(url.as_str())?, url.as_str())
+    }
+
+    /// Parse RSS or Atom `text`, with relative links resolved against `url` of the feed.
+    pub fn parse(text: &str, url: &str) -> Result<Feed, PlayerError> {
+        // Some feeds still carry the RSS 0.91 doctype.
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = Document::parse_with_options(text, options).map_err(|e| invalid(e.to_string()))?;
+        let root = doc.root_element();
+        let base = Url::parse(url).ok();
+        match (root.tag_name().name(), ns(root)) {
+            ("rss", Ns::Rss) => {
+                let channel = child(root, Ns::Rss, "channel").ok_or_else(|| invalid("RSS without channel"))?;
+                Ok(parse_rss(channel, base.as_ref()))
+            }
+            ("feed", Ns::Atom) => Ok(parse_atom(root, base.as_ref())),
+            _ => Err(invalid("not an RSS or Atom feed")),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> PlayerError {
+    PlayerError::UnsupportedFormat(format!("feed: {}", message.into()))
+}
+
+/// Namespace of feed elements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ns {
+    /// RSS elements have none.
+    Rss,
+    Atom,
+    Itunes,
+    Podcast,
+    Psc,
+    Other,
+}
+
+fn ns(node: Node) -> Ns {
+    let Some(uri) = node.tag_name().namespace().map(str::to_ascii_lowercase) else {
+        return Ns::Rss;
+    };
+    // Feeds are sloppy about the exact namespace uris.
+    if uri == "http://www.w3.org/2005/atom" {
+        Ns::Atom
+    } else if uri.contains("itunes.com/dtds") {
+        Ns::Itunes
+    } else if uri.contains("podcastindex") {
+        Ns::Podcast
+    } else if uri.contains("podlove.org/simple-chapters") {
+        Ns::Psc
+    } else {
+        Ns::Other
+    }
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, ns: Ns, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name && self::ns(*n) == ns)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, ns: Ns, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, ns, name).next()
+}
+
+/// Trimmed text content, `None` if empty.
+fn text(node: Node) -> Option<String> {
+    let text: String = node.children().filter_map(|n| n.text()).collect();
+    Some(text.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+fn child_text(node: Node, ns: Ns, name: &'static str) -> Option<String> {
+    child(node, ns, name).and_then(text)
+}
+
+fn resolve(url: &str, base: Option<&Url>) -> String {
+    let url = url.trim();
+    base.and_then(|base| base.join(url).ok()).map_or_else(|| url.to_string(), String::from)
+}
+
+fn link(node: Node, base: Option<&Url>) -> Option<FeedLink> {
+    Some(FeedLink {
+        url: resolve(node.attribute("url")?, base),
+        mime_type: node.attribute("type").map(str::to_string),
+        language: node.attribute("language").map(str::to_string),
+        rel: node.attribute("rel").map(str::to_string),
+    })
+}
+
+fn parse_rss(channel: Node, base: Option<&Url>) -> Feed {
+    let image = child(channel, Ns::Itunes, "image")
+        .and_then(|i| i.attribute("href"))
+        .map(str::to_string)
+        .or_else(|| child(channel, Ns::Rss, "image").and_then(|i| child_text(i, Ns::Rss, "url")))
+        .map(|url| resolve(&url, base));
+    let episodes = children(channel, Ns::Rss, "item")
+        .filter_map(|item| {
+            let enclosure = child(item, Ns::Rss, "enclosure")?;
+            let mut episode = Episode {
+                title: child_text(item, Ns::Rss, "title").or_else(|| child_text(item, Ns::Itunes, "title")),
+                guid: child_text(item, Ns::Rss, "guid"),
+                description: child_text(item, Ns::Rss, "description").or_else(|| child_text(item, Ns::Itunes, "summary")),
+                link: child_text(item, Ns::Rss, "link").map(|l| resolve(&l, base)),
+                url: resolve(enclosure.attribute("url")?, base),
+                length: enclosure.attribute("length").and_then(|l| l.trim().parse().ok()).filter(|&l| l > 0),
+                mime_type: enclosure.attribute("type").map(str::to_string),
+                published: child_text(item, Ns::Rss, "pubDate").and_then(|d| parse_date(&d)),
+                ..Default::default()
+            };
+            extensions(item, base, &mut episode);
+            episode.image = episode.image.or_else(|| image.clone());
+            Some(episode)
+        })
+        .collect();
+
+    Feed {
+        title: child_text(channel, Ns::Rss, "title"),
+        description: child_text(channel, Ns::Rss, "description").or_else(|| child_text(channel, Ns::Itunes, "summary")),
+        link: child_text(channel, Ns::Rss, "link").map(|l| resolve(&l, base)),
+        author: child_text(channel, Ns::Itunes, "author").or_else(|| child_text(channel, Ns::Rss, "managingEditor")),
+        image,
+        language: child_text(channel, Ns::Rss, "language"),
+        episodes,
+    }
+}
+
+fn parse_atom(feed: Node, base: Option<&Url>) -> Feed {
+    fn links<'a, 'input>(node: Node<'a, 'input>, rel: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+        children(node, Ns::Atom, "link").filter(move |l| l.attribute("rel").unwrap_or("alternate") == rel)
+    }
+    let href = |node: Node, rel| links(node, rel).find_map(|l| l.attribute("href")).map(|h| resolve(h, base));
+    let image = child_text(feed, Ns::Atom, "logo")
+        .or_else(|| child_text(feed, Ns::Atom, "icon"))
+        .map(|url| resolve(&url, base));
+    let episodes = children(feed, Ns::Atom, "entry")
+        .filter_map(|entry| {
+            let enclosure = links(entry, "enclosure").next()?;
+            let date = child_text(entry, Ns::Atom, "published").or_else(|| child_text(entry, Ns::Atom, "updated"));
+            let mut episode = Episode {
+                title: child_text(entry, Ns::Atom, "title"),
+                guid: child_text(entry, Ns::Atom, "id"),
+                description: child_text(entry, Ns::Atom, "summary").or_else(|| child_text(entry, Ns::Atom, "content")),
+                link: href(entry, "alternate"),
+                url: resolve(enclosure.attribute("href")?, base),
+                length: enclosure.attribute("length").and_then(|l| l.trim().parse().ok()).filter(|&l| l > 0),
+                mime_type: enclosure.attribute("type").map(str::to_string),
+                published: date.and_then(|d| parse_date(&d)),
+                ..Default::default()
+            };
+            extensions(entry, base, &mut episode);
+            episode.image = episode.image.or_else(|| image.clone());
+            Some(episode)
+        })
+        .collect();
+
+    Feed {
+        title: child_text(feed, Ns::Atom, "title"),
+        description: child_text(feed, Ns::Atom, "subtitle"),
+        link: href(feed, "alternate"),
+        author: child(feed, Ns::Atom, "author").and_then(|a| child_text(a, Ns::Atom, "name")),
+        image,
+        language: feed.attribute(("http://www.w3.org/XML/1998/namespace", "lang")).map(str::to_string),
+        episodes,
+    }
+}
+
+/// iTunes, Podcasting 2.0 and Podlove elements of an RSS item or Atom entry.
+fn extensions(item: Node, base: Option<&Url>, episode: &mut Episode) {
+    episode.duration = child_text(item, Ns::Itunes, "duration").and_then(|d| parse_clock(&d));
+    episode.image = child(item, Ns::Itunes, "image")
+        .and_then(|i| i.attribute("href"))
+        .map(|url| resolve(url, base));
+    episode.chapters = child(item, Ns::Psc, "chapters")
+        .into_iter()
+        .flat_map(|chapters| children(chapters, Ns::Psc, "chapter"))
+        .filter_map(|chapter| {
+            Some(EpisodeChapter {
+                start: parse_clock(chapter.attribute("start")?)?,
+                title: chapter.attribute("title").unwrap_or_default().trim().to_string(),
+                url: chapter.attribute("href").map(|url| resolve(url, base)),
+                image: chapter.attribute("image").map(|url| resolve(url, base)),
+            })
+        })
+        .collect();
+    episode.chapters_url = child(item, Ns::Podcast, "chapters").and_then(|c| link(c, base));
+    episode.transcripts = children(item, Ns::Podcast, "transcript").filter_map(|t| link(t, base)).collect();
+}
+
+/// `[[HH:]MM:]SS[.fff]` or plain seconds, in seconds.
+fn parse_clock(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .split(':')
+        .try_fold(0.0, |total, part| Some(total * 60.0 + part.trim().parse::<f64>().ok()?))
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+}
+
+/// RFC 2822 date of RSS (`Tue, 10 Jun 2003 04:00:00 GMT`), or RFC 3339 of Atom.
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let secs = parse_rfc2822(value).or_else(|| parse_datetime(value))?;
+    let offset = Duration::try_from_secs_f64(secs.abs()).ok()?;
+    if secs >= 0.0 {
+        UNIX_EPOCH.checked_add(offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+/// RFC 2822 date in seconds since the epoch.
+fn parse_rfc2822(value: &str) -> Option<f64> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+    const ZONES: [(&str, &str); 8] = [
+        ("EST", "-05:00"),
+        ("EDT", "-04:00"),
+        ("CST", "-06:00"),
+        ("CDT", "-05:00"),
+        ("MST", "-07:00"),
+        ("MDT", "-06:00"),
+        ("PST", "-08:00"),
+        ("PDT", "-07:00"),
+    ];
+    // Day of week is optional.
+    let value = value.split_once(',').map_or(value, |(_, date)| date);
+    let mut parts = value.split_whitespace();
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?.get(..3)?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    let year = match parts.next()?.parse::<i64>().ok()? {
+        year @ 0..50 => year + 2000,
+        year @ 50..100 => year + 1900,
+        year => year,
+    };
+    let time = parts.next()?;
+    let seconds = if time.matches(':').count() == 1 { ":00" } else { "" };
+    let zone = parts.next().unwrap_or("GMT").to_ascii_uppercase();
+    let zone = match ZONES.iter().find(|(name, _)| *name == zone) {
+        Some((_, offset)) => offset.to_string(),
+        None if zone.len() == 5 && zone.starts_with(['+', '-']) => format!("{}:{}", &zone[..3], &zone[3..]),
+        None => "Z".to_string(),
+    };
+    parse_datetime(&format!("{:04}-{:02}-{:02}T{}{}{}", year, month, day, time, seconds, zone))
+}
+
+#[test]
+fn parses_rss_feed() {
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd"
+     xmlns:podcast="https://podcastindex.org/namespace/1.0" xmlns:psc="http://podlove.org/simple-chapters">
+  <channel>
+    <title>Dasko i Mladja</title>
+    <link>https://daskoimladja.com</link>
+    <description><![CDATA[Radio <b>show</b>]]></description>
+    <language>sr</language>
+    <itunes:author>Dasko</itunes:author>
+    <itunes:image href="https://daskoimladja.com/cover.jpg"/>
+    <item>
+      <title>Episode 2</title>
+      <guid isPermaLink="false">ep-2</guid>
+      <pubDate>Tue, 10 Jun 2003 04:00:00 +0200</pubDate>
+      <enclosure url="/media/ep2.mp3" length="31000000" type="audio/mpeg"/>
+      <itunes:duration>1:02:03</itunes:duration>
+      <itunes:image href="ep2.jpg"/>
+      <podcast:chapters url="https://daskoimladja.com/ep2.json" type="application/json+chapters"/>
+      <podcast:transcript url="https://daskoimladja.com/ep2.vtt" type="text/vtt" language="sr" rel="captions"/>
+      <psc:chapters version="1.2">
+        <psc:chapter start="0" title="Intro"/>
+        <psc:chapter start="05:30.5" title="News" href="https://example.com/news"/>
+      </psc:chapters>
+    </item>
+    <item><title>Announcement without audio</title></item>
+    <item>
+      <title>Episode 1</title>
+      <pubDate>Mon, 9 Jun 03 10:00 EST</pubDate>
+      <enclosure url="https://cdn.example.com/ep1.m4a" length="0" type="audio/mp4"/>
+      <itunes:duration>3600</itunes:duration>
+    </item>
+  </channel>
+</rss>"#;
+    let feed = Feed::parse(rss, "https://daskoimladja.com/feed.xml").unwrap();
+    assert_eq!(feed.title.as_deref(), Some("Dasko i Mladja"));
+    assert_eq!(feed.description.as_deref(), Some("Radio <b>show</b>"));
+    assert_eq!(feed.author.as_deref(), Some("Dasko"));
+    assert_eq!(feed.language.as_deref(), Some("sr"));
+    assert_eq!(feed.episodes.len(), 2);
+
+    let episode = &feed.episodes[0];
+    assert_eq!(episode.url, "https://daskoimladja.com/media/ep2.mp3");
+    assert_eq!(episode.guid.as_deref(), Some("ep-2"));
+    assert_eq!(episode.length, Some(31000000));
+    assert_eq!(episode.mime_type.as_deref(), Some("audio/mpeg"));
+    assert_eq!(episode.duration, Some(3723.0));
+    assert_eq!(episode.published, Some(UNIX_EPOCH + Duration::from_secs(1055210400)));
+    assert_eq!(episode.image.as_deref(), Some("https://daskoimladja.com/ep2.jpg"));
+    assert_eq!(episode.chapters.len(), 2);
+    assert_eq!(episode.chapters[1].start, 330.5);
+    assert_eq!(episode.chapters[1].title, "News");
+    assert_eq!(episode.chapters_url.as_ref().unwrap().mime_type.as_deref(), Some("application/json+chapters"));
+    assert_eq!(episode.transcripts[0].rel.as_deref(), Some("captions"));
+
+    let episode = &feed.episodes[1];
+    assert_eq!(episode.length, None);
+    assert_eq!(episode.duration, Some(3600.0));
+    assert_eq!(episode.published, Some(UNIX_EPOCH + Duration::from_secs(1055170800)));
+    assert_eq!(episode.image.as_deref(), Some("https://daskoimladja.com/cover.jpg"));
+
+    assert!(Feed::parse("<html/>", "https://daskoimladja.com/").is_err());
+}
+
+#[test]
+fn parses_atom_feed() {
+    let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en">
+  <title>Atom cast</title>
+  <link href="https://example.com/"/>
+  <link rel="self" href="https://example.com/atom.xml"/>
+  <author><name>Jane</name></author>
+  <entry>
+    <title>First</title>
+    <id>urn:uuid:1</id>
+    <updated>2024-03-01T12:00:00Z</updated>
+    <summary>Hello</summary>
+    <link href="https://example.com/first"/>
+    <link rel="enclosure" href="first.ogg" type="audio/ogg" length="1234"/>
+  </entry>
+</feed>"#;
+    let feed = Feed::parse(atom, "https://example.com/atom.xml").unwrap();
+    assert_eq!(feed.link.as_deref(), Some("https://example.com/"));
+    assert_eq!(feed.author.as_deref(), Some("Jane"));
+    assert_eq!(feed.language.as_deref(), Some("en"));
+    let episode = &feed.episodes[0];
+    assert_eq!(episode.url, "https://example.com/first.ogg");
+    assert_eq!(episode.link.as_deref(), Some("https://example.com/first"));
+    assert_eq!(episode.length, Some(1234));
+    assert_eq!(episode.published, Some(UNIX_EPOCH + Duration::from_secs(1709294400)));
+}
+
+#[test]
+fn fetches_feed_and_opens_episode() {
+    use crate::test_util::{http_response, http_server, wav_bytes};
+    use crate::Decoder;
+
+    let wav = wav_bytes(8000, 1, 8000, |i| (i % 1000) as i16);
+    let url = http_server(move |req| match req.path.as_str() {
+        "/feed.xml" => {
+            let rss = b"<rss><channel><item><enclosure url=\"episode.wav\" type=\"audio/wav\"/></item></channel></rss>";
+            http_response(200, &[], rss)
+        }
+        "/episode.wav" => http_response(200, &[("content-length", wav.len().to_string())], &wav),
+        _ => http_response(404, &[], b""),
+    });
+
+    let feed = Feed::fetch(&format!("{}/feed.xml", url)).unwrap();
+    let mut decoder = Decoder::open(&feed.episodes[0].url).unwrap();
+    assert_eq!(decoder.duration(), Some(1.0));
+    assert!(decoder.next().is_some());
+    assert_eq!(Feed::fetch(&format!("{}/missing.xml", url)), Err(PlayerError::HttpStatus(404)));
+}
@@ -0,0 +1,397 @@
+//! HTTP Live Streaming (HLS) manifests.
+//!
+//! A master playlist is resolved to an audio rendition, or to the variant carrying the least
+//! video if the audio is muxed. The segments of the media playlist are played by
+//! `SegmentReader`, live playlists are reloaded while playing.
+
+use std::sync::Arc;
+
+use url::Url;
+
+use crate::http::SourceOptions;
+use crate::player_engine::{extension, StatusLink};
+use crate::segments::{manifest_url, ByteRange, Fetcher, InitSection, Manifest, Segment, SegmentList, SegmentReader};
+use crate::PlayerError;
+
+/// Whether `src` is an HLS playlist.
+pub(crate) fn is_hls(src: &str) -> bool {
+    extension(src).is_some_and(|ext| ext.eq_ignore_ascii_case("m3u8"))
+}
+
+/// Fetch the playlist at `src` and open its segments.
+pub(crate) fn open(src: &str, link: Option<StatusLink>, options: &SourceOptions) -> Result<SegmentReader, PlayerError> {
+    let fetcher = Fetcher::new(options, link)?;
+    let mut media_url = manifest_url(src)?;
+    let mut playlist = fetch_playlist(&fetcher, &media_url)?;
+    if let Playlist::Master(master) = &playlist {
+        let url = select_media(master).ok_or(PlayerError::NoSupportedTrack)?;
+        media_url = Url::parse(&url).map_err(|e| PlayerError::Io(e.to_string()))?;
+        playlist = fetch_playlist(&fetcher, &media_url)?;
+    }
+    let Playlist::Media(list) = playlist else {
+        return Err(PlayerError::UnsupportedFormat("nested HLS master playlists".to_string()));
+    };
+    SegmentReader::open(fetcher, Arc::new(HlsManifest { media_url }), list)
+}
+
+/// Media playlist of a live stream.
+struct HlsManifest {
+    media_url: Url,
+}
+
+impl Manifest for HlsManifest {
+    fn reload(&self, fetcher: &Fetcher) -> Result<SegmentList, PlayerError> {
+        match fetch_playlist(fetcher, &self.media_url)? {
+            Playlist::Media(list) => Ok(list),
+            Playlist::Master(_) => Err(PlayerError::UnsupportedFormat("HLS media playlist became a master playlist".to_string())),
+        }
+    }
+}
+
+fn fetch_playlist(fetcher: &Fetcher, url: &Url) -> Result<Playlist, PlayerError> {
+    parse_playlist(&fetcher.fetch_text(url.as_str())?, url)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Playlist {
+    Master(MasterPlaylist),
+    Media(SegmentList),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct MasterPlaylist {
+    variants: Vec<Variant>,
+    renditions: Vec<Rendition>,
+}
+
+/// `EXT-X-STREAM-INF` entry.
+#[derive(Clone, Debug, PartialEq)]
+struct Variant {
+    uri: String,
+    bandwidth: u64,
+    codecs: Option<String>,
+    /// Group id of the audio renditions.
+    audio: Option<String>,
+}
+
+/// `EXT-X-MEDIA` entry of type `AUDIO`.
+#[derive(Clone, Debug, PartialEq)]
+struct Rendition {
+    group_id: String,
+    /// `None` if the audio is muxed into the variants.
+    uri: Option<String>,
+    default: bool,
+    autoselect: bool,
+}
+
+impl Variant {
+    /// Variant declares only audio codecs.
+    fn audio_only(&self) -> bool {
+        const AUDIO_CODECS: [&str; 7] = ["mp4a", "mp3", "ac-3", "ec-3", "opus", "flac", "alac"];
+        self.codecs.as_deref().is_some_and(|codecs| {
+            codecs
+                .split(',')
+                .all(|codec| AUDIO_CODECS.iter().any(|audio| codec.trim().starts_with(audio)))
+        })
+    }
+}
+
+/// Url of the media playlist to play: the default rendition of the audio group of the
+/// highest bandwidth variant, otherwise the highest bandwidth audio only variant, otherwise
+/// the lowest bandwidth variant.
+fn select_media(master: &MasterPlaylist) -> Option<String> {
+    let mut variants: Vec<&Variant> = master.variants.iter().collect();
+    variants.sort_by_key(|v| std::cmp::Reverse(v.bandwidth));
+    for group in variants.iter().filter_map(|v| v.audio.as_ref()) {
+        let renditions: Vec<&Rendition> = master
+            .renditions
+            .iter()
+            .filter(|r| &r.group_id == group && r.uri.is_some())
+            .collect();
+        let rendition = renditions
+            .iter()
+            .find(|r| r.default)
+            .or_else(|| renditions.iter().find(|r| r.autoselect))
+            .or(renditions.first());
+        if let Some(rendition) = rendition {
+            return rendition.uri.clone();
+        }
+    }
+    variants
+        .iter()
+        .find(|v| v.audio_only())
+        .or(variants.last())
+        .map(|v| v.uri.clone())
+}
+
+fn parse_playlist(text: &str, base: &Url) -> Result<Playlist, PlayerError> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next().map(|line| line.trim_start_matches('\u{feff}')) != Some("#EXTM3U") {
+        return Err(PlayerError::UnsupportedFormat("HLS playlist without #EXTM3U".to_string()));
+    }
+    let resolve = |uri: &str| {
+        base.join(uri)
+            .map(String::from)
+            .map_err(|e| PlayerError::UnsupportedFormat(format!("invalid HLS uri {}: {}", uri, e)))
+    };
+
+    let mut master = MasterPlaylist::default();
+    let mut media = SegmentList::default();
+    let mut media_sequence = 0;
+    let mut stream_inf = None;
+    let mut duration = None;
+    let mut range = None;
+    let mut map = None;
+    let mut encrypted = false;
+    // Uri and end of the last byte range, where a range without offset continues.
+    let mut range_end: Option<(String, u64)> = None;
+
+    for line in lines {
+        if let Some(value) = line.strip_prefix("#EXTINF:") {
+            duration = value.split(',').next().and_then(|d| d.trim().parse::<f64>().ok());
+        } else if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            media.target_duration = value.trim().parse().unwrap_or(0.0);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media_sequence = value.trim().parse().unwrap_or(0);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            range = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = attributes(value);
+            let uri = attr(&attrs, "URI")
+                .filter(|uri| !uri.is_empty())
+                .ok_or_else(|| PlayerError::UnsupportedFormat("HLS EXT-X-MAP without URI".to_string()))?;
+            let uri = resolve(uri)?;
+            let range = attr(&attrs, "BYTERANGE").and_then(|r| byte_range(r, None));
+            map = Some(InitSection { uri, range });
+        } else if let Some(value) = line.strip_prefix("#EXT-X-KEY:") {
+            encrypted = attr(&attributes(value), "METHOD").is_some_and(|m| m != "NONE");
+        } else if line == "#EXT-X-ENDLIST" {
+            media.ended = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            stream_inf = Some(value.to_string());
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = attributes(value);
+            if attr(&attrs, "TYPE") == Some("AUDIO") {
+                master.renditions.push(Rendition {
+                    group_id: attr(&attrs, "GROUP-ID").unwrap_or_default().to_string(),
+                    uri: attr(&attrs, "URI").map(resolve).transpose()?,
+                    default: attr(&attrs, "DEFAULT") == Some("YES"),
+                    autoselect: attr(&attrs, "AUTOSELECT") == Some("YES"),
+                });
+            }
+        } else if line.starts_with('#') {
+            // Comment or tag not needed for playback.
+        } else if let Some(value) = stream_inf.take() {
+            let attrs = attributes(&value);
+            master.variants.push(Variant {
+                uri: resolve(line)?,
+                bandwidth: attr(&attrs, "BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+                codecs: attr(&attrs, "CODECS").map(str::to_string),
+                audio: attr(&attrs, "AUDIO").map(str::to_string),
+            });
+        } else {
+            let uri = resolve(line)?;
+            let continues = range_end.as_ref().filter(|(end_uri, _)| *end_uri == uri).map(|(_, end)| *end);
+            let range = range.take().and_then(|r| byte_range(&r, continues));
+            range_end = range.as_ref().map(|r| (uri.clone(), r.start + r.len));
+            media.segments.push(Segment {
+                sequence: media_sequence + media.segments.len() as u64,
+                uri,
+                duration: duration.take().unwrap_or(media.target_duration),
+                range,
+                map: map.clone(),
+                encrypted,
+            });
+        }
+    }
+
+    if master.variants.is_empty() {
+        Ok(Playlist::Media(media))
+    } else {
+        Ok(Playlist::Master(master))
+    }
+}
+
+/// Attribute list (`NAME=value,NAME="quoted, value"`) as names and unquoted values.
+fn attributes(list: &str) -> Vec<(&str, &str)> {
+    let mut attrs = Vec::new();
+    let mut rest = list.trim();
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, next) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (value, next.split_once(',').map_or("", |(_, next)| next))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        attrs.push((name.trim(), value));
+        rest = next.trim_start();
+    }
+    attrs
+}
+
+fn attr<'a>(attrs: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    attrs.iter().find(|(n, _)| *n == name).map(|(_, value)| *value)
+}
+
+/// `<len>[@<offset>]`. Without offset the range starts at `continues`.
+fn byte_range(value: &str, continues: Option<u64>) -> Option<ByteRange> {
+    let (len, start) = match value.split_once('@') {
+        Some((len, start)) => (len, start.trim().parse().ok()?),
+        None => (value, continues.unwrap_or(0)),
+    };
+    Some(ByteRange {
+        start,
+        len: len.trim().parse().ok()?,
+    })
+}
+
+#[test]
+fn parses_playlists_and_selects_audio() {
+    let base = Url::parse("https://cdn.example.com/show/master.m3u8").unwrap();
+    let master = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",LANGUAGE=\"en\",URI=\"audio/en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Deutsch\",DEFAULT=YES,URI=\"audio/de.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,CODECS=\"avc1.4d401e,mp4a.40.2\",AUDIO=\"aac\"
+video/low.m3u8
+";
+    let Ok(Playlist::Master(master)) = parse_playlist(master, &base) else {
+        panic!("not a master playlist");
+    };
+    assert_eq!(select_media(&master).as_deref(), Some("https://cdn.example.com/show/audio/de.m3u8"));
+
+    let muxed = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,CODECS=\"avc1.4d401e,mp4a.40.2\"
+hd.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.5\"
+audio.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=500000,CODECS=\"avc1.4d401e,mp4a.40.2\"
+sd.m3u8
+";
+    let Ok(Playlist::Master(master)) = parse_playlist(muxed, &base) else {
+        panic!("not a master playlist");
+    };
+    assert_eq!(select_media(&master).as_deref(), Some("https://cdn.example.com/show/audio.m3u8"));
+
+    let media = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXT-X-MEDIA-SEQUENCE:42
+#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"700@0\"
+#EXTINF:6.0,
+#EXT-X-BYTERANGE:1000@700
+main.mp4
+#EXTINF:4.5,
+#EXT-X-BYTERANGE:800
+main.mp4
+#EXT-X-ENDLIST
+";
+    let Ok(Playlist::Media(media)) = parse_playlist(media, &base) else {
+        panic!("not a media playlist");
+    };
+    assert!(media.ended);
+    assert_eq!(media.segments[1].sequence, 43);
+    assert_eq!(media.duration(), 10.5);
+    assert_eq!(media.segment_at(7.0), 1);
+    assert_eq!(media.start_of(1), 6.0);
+    assert_eq!(media.segments[1].range, Some(ByteRange { start: 1700, len: 800 }));
+    let map = media.segments[1].map.as_ref().unwrap();
+    assert_eq!(map.uri, "https://cdn.example.com/show/init.mp4");
+    assert_eq!(map.range, Some(ByteRange { start: 0, len: 700 }));
+
+    assert!(parse_playlist("not a playlist", &base).is_err());
+    let no_uri = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MAP:BYTERANGE=\"700@0\"\n#EXTINF:6.0,\nmain.mp4\n";
+    assert!(parse_playlist(no_uri, &base).is_err());
+}
+
+#[test]
+fn decodes_and_seeks_segments() {
+    use crate::test_util::{http_response, http_server, wav_bytes};
+    use crate::Decoder;
+
+    // One second segments of PCM after a WAV header as init section.
+    let wav = wav_bytes(8000, 1, 32000, |i| (i % 1000) as i16);
+    let (init, pcm) = wav.split_at(44);
+    let (init, pcm) = (init.to_vec(), pcm.to_vec());
+    let mut playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:1\n#EXT-X-MAP:URI=\"init.wav\"\n".to_string();
+    for i in 0..4 {
+        playlist.push_str(&format!("#EXTINF:1.0,\nseg{}.pcm\n", i));
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+
+    let url = http_server(move |req| {
+        let body = match req.path.as_str() {
+            "/radio/index.m3u8" => playlist.as_bytes().to_vec(),
+            "/radio/init.wav" => init.clone(),
+            path => match path.strip_prefix("/radio/seg").and_then(|p| p.strip_suffix(".pcm")) {
+                Some(i) => pcm.chunks(16000).nth(i.parse().unwrap()).unwrap().to_vec(),
+                None => return http_response(404, &[], b""),
+            },
+        };
+        http_response(200, &[("content-length", body.len().to_string())], &body)
+    });
+
+    let mut decoder = Decoder::open(&format!("{}/radio/index.m3u8", url)).unwrap();
+    assert_eq!(decoder.duration(), Some(4.0));
+    let samples: Vec<f32> = decoder.by_ref().flat_map(|b| b.unwrap().samples).collect();
+    assert_eq!(samples.len(), 32000);
+    assert_eq!(samples[12345], 345.0 / 32768.0);
+
+    // Seeks land on the start of the segment containing the target.
+    assert_eq!(decoder.seek(2.5).unwrap(), 2.0);
+    let block = decoder.next().unwrap().unwrap();
+    assert_eq!(block.timestamp, 2.0);
+    assert_eq!(block.samples[..2], [0.0, 1.0 / 32768.0]);
+}
+
+#[test]
+fn decodes_and_seeks_fragmented_mp4() {
+    use crate::test_util::{fmp4_init, fmp4_segment, http_response, http_server};
+    use crate::Decoder;
+
+    // One second segments with absolute decode times after an fMP4 init section.
+    let init = fmp4_init(8000);
+    let segments: Vec<Vec<u8>> = (0..4u32)
+        .map(|i| {
+            let samples: Vec<i16> = (i * 8000..(i + 1) * 8000).map(|n| (n % 1000) as i16).collect();
+            fmp4_segment(i + 1, i as u64 * 8000, &samples)
+        })
+        .collect();
+    let mut playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:1\n#EXT-X-MAP:URI=\"init.mp4\"\n".to_string();
+    for i in 0..4 {
+        playlist.push_str(&format!("#EXTINF:1.0,\nseg{}.m4s\n", i));
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+
+    let url = http_server(move |req| {
+        let body = match req.path.as_str() {
+            "/radio/index.m3u8" => playlist.as_bytes().to_vec(),
+            "/radio/init.mp4" => init.clone(),
+            path => match path.strip_prefix("/radio/seg").and_then(|p| p.strip_suffix(".m4s")) {
+                Some(i) => segments[i.parse::<usize>().unwrap()].clone(),
+                None => return http_response(404, &[], b""),
+            },
+        };
+        http_response(200, &[("content-length", body.len().to_string())], &body)
+    });
+
+    let mut decoder = Decoder::open(&format!("{}/radio/index.m3u8", url)).unwrap();
+    assert_eq!(decoder.duration(), Some(4.0));
+    let mut first = None;
+    let mut samples = Vec::new();
+    for block in decoder.by_ref() {
+        let block = block.unwrap();
+        first.get_or_insert(block.timestamp);
+        samples.extend(block.samples);
+    }
+    assert_eq!(first, Some(0.0));
+    assert_eq!(samples.len(), 32000);
+    assert_eq!(samples[12345], 345.0 / 32768.0);
+
+    // Timestamps after a seek count from the start of the list, not twice the segment start.
+    assert_eq!(decoder.seek(2.5).unwrap(), 2.0);
+    let block = decoder.next().unwrap().unwrap();
+    assert_eq!(block.timestamp, 2.0);
+    let rest: Vec<f32> = block.samples.into_iter().chain(decoder.flat_map(|b| b.unwrap().samples)).collect();
+    assert_eq!(rest.len(), 16000);
+    assert_eq!(rest[..2], [0.0, 1.0 / 32768.0]);
+}
@@ -0,0 +1,163 @@
+//! ICY (Shoutcast/Icecast) metadata of internet radio streams.
+//!
+//! With the `Icy-MetaData: 1` request header, servers interleave a metadata block after every
+//! `icy-metaint` bytes of audio. `IcyReader` removes the blocks before the audio reaches the
+//! decoder and reports "now playing" changes.
+
+use std::io::{self, Read};
+
+use crate::player_engine::{PlayerStatus, StatusLink};
+
+/// Station info from the `icy-*` response headers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamInfo {
+    /// `icy-name`
+    pub name: Option<String>,
+    /// `icy-genre`
+    pub genre: Option<String>,
+    /// `icy-br`, bitrate in kbit/s.
+    pub bitrate: Option<u32>,
+    /// `icy-url`, station homepage.
+    pub url: Option<String>,
+    /// `icy-description`
+    pub description: Option<String>,
+}
+
+impl StreamInfo {
+    /// Info from response headers, `None` if there are no `icy-*` headers.
+    pub(crate) fn from_headers<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Option<Self> {
+        let text = |name| header(name).map(|v: &str| v.trim().to_string()).filter(|v| !v.is_empty());
+        let info = StreamInfo {
+            name: text("icy-name"),
+            genre: text("icy-genre"),
+            bitrate: header("icy-br").and_then(|v| v.split(',').next()?.trim().parse().ok()),
+            url: text("icy-url"),
+            description: text("icy-description"),
+        };
+        (info != StreamInfo::default()).then_some(info)
+    }
+}
+
+/// Current song of a radio stream, from the in-band `StreamTitle` and `StreamUrl` fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NowPlaying {
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+impl NowPlaying {
+    /// Parse a metadata block like `StreamTitle='Artist - Song';StreamUrl='';`.
+    pub(crate) fn parse(block: &[u8]) -> Self {
+        let block = block.split(|&b| b == 0).next().unwrap_or_default();
+        // Mostly UTF-8, older servers send Latin-1.
+        let text = match std::str::from_utf8(block) {
+            Ok(text) => text.to_string(),
+            Err(_) => block.iter().map(|&b| b as char).collect(),
+        };
+
+        let mut now_playing = NowPlaying::default();
+        let mut rest = text.as_str();
+        while let Some((key, value)) = rest.split_once("='") {
+            // Values may contain `'`, only `';` ends a field.
+            let (value, next) = value.split_once("';").unwrap_or((value.trim_end_matches('\''), ""));
+            let value = Some(value.to_string()).filter(|v| !v.is_empty());
+            match key.trim() {
+                "StreamTitle" => now_playing.title = value,
+                "StreamUrl" => now_playing.url = value,
+                _ => {}
+            }
+            rest = next;
+        }
+        now_playing
+    }
+}
+
+/// Reader which strips ICY metadata blocks from `inner` and sends `PlayerStatus::NowPlaying`
+/// when the song changes.
+pub(crate) struct IcyReader<R> {
+    inner: R,
+    metaint: usize,
+    /// Audio bytes left before the next metadata block.
+    until_meta: usize,
+    now_playing: Option<NowPlaying>,
+    tx: Option<StatusLink>,
+}
+
+impl<R: Read> IcyReader<R> {
+    pub fn new(inner: R, metaint: usize, tx: Option<StatusLink>) -> Self {
+        IcyReader {
+            inner,
+            metaint,
+            until_meta: metaint,
+            now_playing: None,
+            tx,
+        }
+    }
+
+    /// Read the metadata block. Returns `false` if the stream ended before it.
+    fn read_metadata(&mut self) -> io::Result<bool> {
+        let mut len = [0u8];
+        if let Err(e) = self.inner.read_exact(&mut len) {
+            return match e.kind() {
+                io::ErrorKind::UnexpectedEof => Ok(false),
+                _ => Err(e),
+            };
+        }
+        // Zero length: metadata unchanged.
+        if len[0] > 0 {
+            let mut block = vec![0u8; len[0] as usize * 16];
+            self.inner.read_exact(&mut block)?;
+            let now_playing = NowPlaying::parse(&block);
+            if self.now_playing.as_ref() != Some(&now_playing) {
+                if let Some(tx) = &self.tx {
+                    tx.send(PlayerStatus::NowPlaying(now_playing.clone()));
+                }
+                self.now_playing = Some(now_playing);
+            }
+        }
+        self.until_meta = self.metaint;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for IcyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.until_meta == 0 && !self.read_metadata()? {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.until_meta);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.until_meta -= n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn parse_now_playing() {
+    let np = NowPlaying::parse(b"StreamTitle='Guns N' Roses - Patience';StreamUrl='http://x.y/';\0\0\0");
+    assert_eq!(np.title.as_deref(), Some("Guns N' Roses - Patience"));
+    assert_eq!(np.url.as_deref(), Some("http://x.y/"));
+
+    let np = NowPlaying::parse(b"StreamTitle='Caf\xe9';");
+    assert_eq!(np.title.as_deref(), Some("Caf\u{e9}"));
+    assert_eq!(np.url, None);
+}
+
+#[test]
+fn icy_reader_strips_metadata() {
+    let block = b"StreamTitle='A';";
+    let mut stream = b"abcd".to_vec();
+    stream.push(1);
+    stream.extend_from_slice(block);
+    stream.extend_from_slice(b"efgh\0ij");
+
+    let link = StatusLink::default();
+    let mut reader = IcyReader::new(io::Cursor::new(stream), 4, Some(link.clone()));
+    let mut audio = Vec::new();
+    reader.read_to_end(&mut audio).unwrap();
+    assert_eq!(audio, b"abcdefghij");
+    assert_eq!(reader.now_playing.unwrap().title.as_deref(), Some("A"));
+}
@@ -1,22 +1,140 @@
+#[cfg(feature = "playback")]
 mod cpalaudio;
+pub mod cache;
+pub mod chapters;
+mod dash;
+pub mod decoder;
+pub mod download;
+pub mod feed;
+mod hls;
+pub mod http;
+pub mod icy;
+pub mod metadata;
+mod mpegts;
+pub mod output;
 pub mod player_engine;
+pub mod playlist;
+mod queue;
 mod resampler;
-mod url_source;
+mod segments;
+#[cfg(test)]
+mod test_util;
 mod url_source_buff;
+mod volume;
 
 use std::sync::{Arc, RwLock};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Url2AudioError {
     #[error("HTTP request failed: {0}")]
-    Http(#[from] ureq::Error),
+    Http(Box<ureq::Error>),
+    #[error("HTTP status {0}")]
+    Status(u16),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("No content-length header")]
     NoContentLength,
 }
 
+impl From<ureq::Error> for Url2AudioError {
+    fn from(e: ureq::Error) -> Self {
+        Url2AudioError::Http(Box::new(e))
+    }
+}
+
+/// Error reported by the player through `PlayerStatus::Error` and `Player::error()`.
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum PlayerError {
+    /// Server responded with a non-success HTTP status code.
+    #[error("HTTP status {0}")]
+    HttpStatus(u16),
+    /// Network or file I/O failure.
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// Container or codec not supported.
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+    /// Stream has no track that can be decoded.
+    #[error("No supported audio track")]
+    NoSupportedTrack,
+    /// Malformed or corrupt audio data.
+    #[error("Decode error: {0}")]
+    Decode(String),
+    /// Audio output device could not be opened or stopped working.
+    #[error("Audio device unavailable: {0}")]
+    AudioDeviceUnavailable(String),
+    /// Seek could not be performed. Playback continues from the current position.
+    #[error("Seek failed: {0}")]
+    SeekFailed(String),
+    /// Stream ended unexpectedly.
+    #[error("End of stream")]
+    EndOfStream,
+    /// Seek requested on a live stream. Playback continues.
+    #[error("Live stream is not seekable")]
+    NotSeekable,
+    /// Downloaded file doesn't match the length or range the server announced.
+    #[error("Download verification failed: {0}")]
+    VerificationFailed(String),
+}
+
+impl From<Url2AudioError> for PlayerError {
+    fn from(e: Url2AudioError) -> Self {
+        match e {
+            Url2AudioError::Http(e) => match *e {
+                ureq::Error::Status(code, _) => PlayerError::HttpStatus(code),
+                e => PlayerError::Io(e.to_string()),
+            },
+            Url2AudioError::Status(code) => PlayerError::HttpStatus(code),
+            Url2AudioError::Io(e) => PlayerError::from(e),
+            Url2AudioError::NoContentLength => PlayerError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for PlayerError {
+    fn from(e: std::io::Error) -> Self {
+        // `UrlSourceBuf` wraps HTTP failures (e.g. on range re-requests) into io errors.
+        if let Some(Url2AudioError::Status(code)) = e.get_ref().and_then(|e| e.downcast_ref()) {
+            return PlayerError::HttpStatus(*code);
+        }
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => PlayerError::EndOfStream,
+            _ => PlayerError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<symphonia::core::errors::Error> for PlayerError {
+    fn from(e: symphonia::core::errors::Error) -> Self {
+        use symphonia::core::errors::Error;
+        match e {
+            Error::IoError(e) => PlayerError::from(e),
+            Error::DecodeError(e) => PlayerError::Decode(e.to_string()),
+            Error::SeekError(_) => PlayerError::SeekFailed(e.to_string()),
+            Error::Unsupported(e) => PlayerError::UnsupportedFormat(e.to_string()),
+            Error::LimitError(_) | Error::ResetRequired => PlayerError::Decode(e.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "playback")]
+pub use cpalaudio::{hosts, output_devices, OutputConfig, OutputDevice};
+pub use cache::DiskCache;
+pub use chapters::Chapter;
+pub use decoder::{DecodedAudio, Decoder, DecoderBuilder};
+pub use download::Downloader;
+pub use feed::{Episode, Feed};
+pub use http::{Auth, HttpConfig, HttpResponse, HttpTransport, ReadAhead, ReconnectPolicy, UreqTransport};
+pub use icy::{NowPlaying, StreamInfo};
+pub use metadata::{Metadata, Picture};
+pub use output::DeviceId;
+pub use playlist::{parse_playlist, PlaylistEntry};
+pub use symphonia::core::formats::SeekMode;
+
 use crossbeam_channel::{unbounded, Receiver, Sender};
+use http::SourceOptions;
+use output::AudioSink;
 use player_engine::Playing;
 
 use crate::player_engine::{PlayerActions, PlayerEngine, PlayerState, PlayerStatus};
@@ -39,35 +157,104 @@ impl Player {
     /// When `player.pause()` the command message for pausing will be sent, and it will be 
     /// executed in player_engine's thread's next loop.
     pub fn new() -> Self {
-        let (tx, rx) = unbounded();
-        let (tx_status, rx_status) = unbounded();
-        let (tx_events, rx_events) = unbounded();
-        let mut to_ret = Player {
-            inner_player: Arc::new(RwLock::new(PlayerEngine::new(
-                rx.clone(),
-                tx_status.clone(),
-            ))),
-            tx,
-            rx_status,
-            state: Arc::new(RwLock::new(PlayerState {
-                playing: Playing::Playing,
-                duration: 0.0,
-                position: 0.0,
-                pending_seek: None,
-                error: None,
-                chunks: Default::default(),
-            })),
-            events_rx: rx_events,
-        };
-        to_ret.inner_thread(tx_events);
-        to_ret
+        Self::builder().build()
+    }
+
+    /// Builder for a player with non-default options.
+    pub fn builder() -> PlayerBuilder {
+        PlayerBuilder::default()
     }
 
     /// Open stream from provided url (`src`). Playback will start immediately.
+    /// Besides `http(s)://` urls, `src` can be a local file path or a `file://` url.
+    /// Playlists (`.m3u`, `.pls`, `.xspf` or a playlist content type) play their first
+    /// entry that opens, the next ones are fallbacks.
+    /// Replaces the queue with `src` as the only entry.
     pub fn open(&mut self, src: &str) {
         let _ = self.tx.send(PlayerActions::Open(src.to_string()));
     }
 
+    /// Append `src` to the end of the queue. If nothing is playing, playback of `src` starts.
+    /// When the current entry ends, the next one continues without a gap. It is opened
+    /// in background shortly before the current one ends, which requires the duration of the
+    /// current entry: after streams of unknown length (e.g. live radio) the next entry is
+    /// opened only when they end, so there is a gap.
+    pub fn enqueue(&self, src: &str) {
+        let _ = self.tx.send(PlayerActions::Enqueue(src.to_string()));
+    }
+
+    /// Insert `src` into the queue at `index` (clamped to the queue length).
+    pub fn insert(&self, index: usize, src: &str) {
+        let _ = self.tx.send(PlayerActions::Insert(index, src.to_string()));
+    }
+
+    /// Remove queue entry at `index`. If it is the current entry, the entry taking its
+    /// place starts playing, or playback stops if there is none.
+    pub fn remove(&self, index: usize) {
+        let _ = self.tx.send(PlayerActions::Remove(index));
+    }
+
+    /// Remove all queue entries and stop playback.
+    pub fn clear_queue(&self) {
+        let _ = self.tx.send(PlayerActions::ClearQueue);
+    }
+
+    /// Skip to the next queue entry.
+    pub fn skip_next(&self) {
+        let _ = self.tx.send(PlayerActions::Next);
+    }
+
+    /// Skip to the previous queue entry. If the current entry has played for more than
+    /// 3 seconds (or there is no previous entry) it is restarted instead, live streams are
+    /// reconnected.
+    pub fn skip_previous(&self) {
+        let _ = self.tx.send(PlayerActions::Previous);
+    }
+
+    /// Start playing queue entry at `index`.
+    pub fn jump_to(&self, index: usize) {
+        let _ = self.tx.send(PlayerActions::JumpTo(index));
+    }
+
+    /// Queue entries.
+    pub fn queue(&self) -> Vec<String> {
+        self.state.read().unwrap().queue.clone()
+    }
+
+    /// Index of the current queue entry.
+    pub fn queue_index(&self) -> Option<usize> {
+        self.state.read().unwrap().queue_index
+    }
+
+    /// Chapters of the current track (ID3v2 `CHAP` frames, MP4 chapter track or Nero
+    /// chapters), ordered by start.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.state.read().unwrap().chapters.clone()
+    }
+
+    /// Index of the chapter being played, `None` if the track has no chapters or playback is
+    /// before the first one.
+    pub fn current_chapter(&self) -> Option<usize> {
+        self.state.read().unwrap().chapter
+    }
+
+    /// Seek to the start of the next chapter.
+    pub fn next_chapter(&self) {
+        let _ = self.tx.send(PlayerActions::NextChapter);
+    }
+
+    /// Seek to the start of the previous chapter. If the current chapter has played for more
+    /// than 3 seconds it is restarted instead.
+    pub fn previous_chapter(&self) {
+        let _ = self.tx.send(PlayerActions::PreviousChapter);
+    }
+
+    /// Seek to the start of the chapter at `index`. Like `seek`, chapter navigation of live
+    /// streams reports `PlayerError::NotSeekable`.
+    pub fn seek_to_chapter(&self, index: usize) {
+        let _ = self.tx.send(PlayerActions::SeekToChapter(index));
+    }
+
     fn inner_thread(&mut self, tx_events: Sender<PlayerStatus>) {
         let player = self.inner_player.clone();
 
@@ -78,53 +265,120 @@ impl Player {
 
         let rx1 = self.rx_status.clone();
         let s = self.state.clone();
-        let _ = std::thread::spawn(move || loop {
-            match rx1.recv() {
-                Ok(a) => {
-                    let mut state = s.write().unwrap();
-                    match a {
-                        PlayerStatus::SendPlaying(ref playing) => {
-                            state.playing = playing.clone();
-                            let _ = tx_events.send(a);
+        let _ = std::thread::spawn(move || {
+            while let Ok(a) = rx1.recv() {
+                let mut state = s.write().unwrap();
+                match a {
+                    PlayerStatus::SendPlaying(ref playing) => {
+                        state.playing = playing.clone();
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::SendTimeStats(position, duration) => {
+                        state.duration = duration;
+                        if state.pending_seek.is_none() {
+                            state.position = position;
                         }
-                        PlayerStatus::SendTimeStats(position, duration) => {
-                            state.duration = duration;
-                            if state.pending_seek.is_none() {
-                                state.position = position;
-                            }
+                    }
+                    PlayerStatus::Error(PlayerError::SeekFailed(_) | PlayerError::NotSeekable) => {
+                        // Failed seek is not fatal, playback continues.
+                        state.pending_seek = None;
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Error(ref err) => {
+                        state.error = Some(err.clone());
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Finished => {
+                        state.playing = Playing::Finished;
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::ClearError => {
+                        state.error = None;
+                        state.pending_seek = None;
+                        state.chunks = Default::default();
+                        state.metadata = None;
+                        state.stream_info = None;
+                        state.now_playing = None;
+                        state.live = false;
+                        state.chapters = Vec::new();
+                        state.chapter = None;
+                    }
+                    PlayerStatus::ChunkAdded(start, end) => {
+                        state.chunks.push((start, end));
+                    },
+                    PlayerStatus::Seeked(t) => {
+                        state.pending_seek = None;
+                        state.position = t;
+                        let _ = tx_events.send(PlayerStatus::Seeked(t));
+                    },
+                    PlayerStatus::QueueChanged(ref queue, index) => {
+                        state.queue = queue.clone();
+                        state.queue_index = index;
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Volume(volume, muted) => {
+                        state.volume = volume;
+                        state.muted = muted;
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::OutputDevice(ref device) => {
+                        state.output_device = device.clone();
+                        if matches!(state.error, Some(PlayerError::AudioDeviceUnavailable(_))) {
+                            state.error = None;
                         }
-                        PlayerStatus::Error(ref err) => {
-                            if state.position - state.duration >= -1.0 {
-                                state.error = None;
-                                state.playing = Playing::Finished;
-                            } else {
-                                state.error = Some(err.clone());
-                            }
-                            let _ = tx_events.send(a);
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Metadata(ref metadata) => {
+                        state.metadata = Some(metadata.clone());
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Live(live) => {
+                        state.live = live;
+                    }
+                    PlayerStatus::StreamInfo(ref info) => {
+                        state.stream_info = Some(info.clone());
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::NowPlaying(ref now_playing) => {
+                        state.now_playing = Some(now_playing.clone());
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::BufferingStarted => {
+                        state.playing = Playing::Buffering(0.0);
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Buffering(fill) => {
+                        if let Playing::Buffering(_) = state.playing {
+                            state.playing = Playing::Buffering(fill);
                         }
-                        PlayerStatus::ClearError => {
-                            state.error = None;
-                            state.chunks = Default::default();
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::BufferingEnded => {
+                        if let Playing::Buffering(_) = state.playing {
+                            state.playing = Playing::Playing;
                         }
-                        PlayerStatus::ChunkAdded(start, end) => {
-                            state.chunks.push((start, end));
-                        },
-                        PlayerStatus::Seeked(t) => {
-                            state.pending_seek = None;
-                            state.position = t;
-                            let _ = tx_events.send(PlayerStatus::Seeked(t));
-                        },
-                        PlayerStatus::Opened(_) | PlayerStatus::Closed => {
-                            let _ = tx_events.send(a);
-                        },
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::Chapters(ref chapters) => {
+                        state.chapters = chapters.clone();
+                        let _ = tx_events.send(a);
+                    }
+                    PlayerStatus::ChapterChanged(chapter) => {
+                        state.chapter = chapter;
+                        let _ = tx_events.send(a);
                     }
+                    PlayerStatus::Opened(_)
+                    | PlayerStatus::Closed
+                    | PlayerStatus::Reconnecting(_)
+                    | PlayerStatus::Reconnected => {
+                        let _ = tx_events.send(a);
+                    },
                 }
-                Err(_) => break,
             }
         });
     }
 
-    /// Start playback (if paused)
+    /// Start playback (if paused). If playback has finished, it starts again from the beginning.
     pub fn play(&self) {
         let _ = self.tx.send(PlayerActions::Resume);
     }
@@ -139,6 +393,66 @@ impl Player {
         let _ = self.tx.send(PlayerActions::Close);
     }
 
+    /// Set linear output volume in range 0.0 - 1.0 (values outside are clamped).
+    /// Volume is kept when opening other streams.
+    pub fn set_volume(&self, volume: f32) {
+        let _ = self.tx.send(PlayerActions::SetVolume(volume));
+    }
+
+    /// Current volume (0.0 - 1.0). Not affected by mute.
+    pub fn volume(&self) -> f32 {
+        self.state.read().unwrap().volume
+    }
+
+    /// Silence the output, keeping the volume setting.
+    pub fn mute(&self) {
+        let _ = self.tx.send(PlayerActions::SetMute(true));
+    }
+
+    /// Restore output to the volume set before muting.
+    pub fn unmute(&self) {
+        let _ = self.tx.send(PlayerActions::SetMute(false));
+    }
+
+    /// Whether output is muted.
+    pub fn is_muted(&self) -> bool {
+        self.state.read().unwrap().muted
+    }
+
+    /// Play on `device` (see `output_devices()`), or on the system default device if `None`.
+    /// Can be changed during playback, playback continues from the current position.
+    /// If the device is not available `PlayerError::AudioDeviceUnavailable` is reported,
+    /// selecting another device recovers from it.
+    pub fn set_output_device(&self, device: Option<DeviceId>) {
+        let _ = self.tx.send(PlayerActions::SetOutputDevice(device));
+    }
+
+    /// Selected output device, `None` for the system default.
+    pub fn output_device(&self) -> Option<DeviceId> {
+        self.state.read().unwrap().output_device.clone()
+    }
+
+    /// Is the current source a live stream (internet radio): not seekable, unknown duration.
+    /// Position of a live stream is the time played since it was opened.
+    pub fn is_live(&self) -> bool {
+        self.state.read().unwrap().live
+    }
+
+    /// Station name, genre and bitrate of the current internet radio stream (ICY headers).
+    pub fn stream_info(&self) -> Option<StreamInfo> {
+        self.state.read().unwrap().stream_info.clone()
+    }
+
+    /// Current song of an internet radio stream (ICY `StreamTitle`/`StreamUrl`).
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        self.state.read().unwrap().now_playing.clone()
+    }
+
+    /// Tags and cover art of the current track. `None` until a track is opened.
+    pub fn metadata(&self) -> Option<Metadata> {
+        self.state.read().unwrap().metadata.clone()
+    }
+
     /// Is player in Playing state.
     pub fn is_playing(&self) -> Playing {
         self.state.read().unwrap().playing.clone()
@@ -152,7 +466,7 @@ impl Player {
     }
 
     /// seek to time from the beginning.
-    /// `time` is in seconds
+    /// `time` is in seconds. Live streams can't seek, `PlayerError::NotSeekable` is reported.
     pub fn seek(&self, time: f64) {
         let _ = self.tx.send(PlayerActions::Seek(time));
     }
@@ -180,8 +494,8 @@ impl Player {
         self.state.read().unwrap().error.is_some()
     }
 
-    /// Current error message (if any)
-    pub fn error(&self) -> Option<String> {
+    /// Current error (if any)
+    pub fn error(&self) -> Option<PlayerError> {
         self.state.read().unwrap().error.clone()
     }
 
@@ -209,3 +523,402 @@ impl Player {
         format!("{}:{:0>2}:{:0>4.1}", hours, mins, secs)
     }
 }
+
+/// Builder for `Player` with non-default options.
+///
+/// ```no_run
+/// use url2audio::{output::NullSink, Player};
+///
+/// let player = Player::builder().sink(NullSink::realtime()).build();
+/// ```
+#[derive(Default)]
+pub struct PlayerBuilder {
+    sink: Option<Box<dyn AudioSink>>,
+    seek_mode: Option<SeekMode>,
+    preroll: Option<f64>,
+    source_options: SourceOptions,
+}
+
+impl PlayerBuilder {
+    /// Sink the player writes audio to. Default is `CpalSink`, playing on the system
+    /// default output device, or a real time `NullSink` without the `playback` feature.
+    pub fn sink(mut self, sink: impl AudioSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// `SeekMode::Coarse` (default) seeks to the nearest packet before the requested time.
+    /// `SeekMode::Accurate` also discards decoded audio before it, so playback continues
+    /// exactly at the requested time. Either way `PlayerStatus::Seeked` reports the position
+    /// playback continues from.
+    pub fn seek_mode(mut self, mode: SeekMode) -> Self {
+        self.seek_mode = Some(mode);
+        self
+    }
+
+    /// Reconnect of HTTP sources after network failures. Default is
+    /// `ReconnectPolicy::default()`: 5 attempts, 0.5s delay doubling up to 8s.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.source_options.reconnect = policy;
+        self
+    }
+
+    /// How much of HTTP sources is downloaded in background ahead of the playback position.
+    /// Default is `ReadAhead::Seconds(30.0)`.
+    pub fn read_ahead(mut self, read_ahead: ReadAhead) -> Self {
+        self.source_options.read_ahead = read_ahead;
+        self
+    }
+
+    /// Headers, user agent, authorization, timeouts, proxy and redirect limit of the requests
+    /// of HTTP sources, including Range and reconnect requests.
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.source_options.http = http;
+        self
+    }
+
+    /// Make the requests of HTTP sources with `transport` instead of ureq, e.g. to share the
+    /// application's HTTP client. `HttpConfig` headers and authorization are still applied.
+    pub fn transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.source_options.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Store downloaded chunks of HTTP sources on disk, so playing a url again doesn't
+    /// download the stored parts. Live streams are not cached.
+    pub fn cache(mut self, cache: DiskCache) -> Self {
+        self.source_options.cache = Some(cache);
+        self
+    }
+
+    /// Seconds of audio downloaded ahead of the playback position before playback of an HTTP
+    /// source starts after open, seek or resume, and continues after buffering. Default is 2
+    /// seconds, 0 starts as soon as any data is available.
+    pub fn preroll(mut self, secs: f64) -> Self {
+        self.preroll = Some(secs);
+        self
+    }
+
+    /// Create the player. Same as `Player::new()` apart from the options set.
+    pub fn build(self) -> Player {
+        let (tx, rx) = unbounded();
+        let (tx_status, rx_status) = unbounded();
+        let (tx_events, rx_events) = unbounded();
+        let mut engine = PlayerEngine::new(rx.clone(), tx_status.clone());
+        if let Some(sink) = self.sink {
+            engine.sink = sink;
+        }
+        if let Some(mode) = self.seek_mode {
+            engine.seek_mode = mode;
+        }
+        if let Some(preroll) = self.preroll {
+            engine.preroll = preroll;
+        }
+        engine.source_options = self.source_options;
+        let mut to_ret = Player {
+            inner_player: Arc::new(RwLock::new(engine)),
+            tx,
+            rx_status,
+            state: Arc::new(RwLock::new(PlayerState {
+                playing: Playing::Playing,
+                duration: 0.0,
+                position: 0.0,
+                pending_seek: None,
+                error: None,
+                chunks: Default::default(),
+                queue: Default::default(),
+                queue_index: None,
+                volume: 1.0,
+                muted: false,
+                output_device: None,
+                metadata: None,
+                stream_info: None,
+                now_playing: None,
+                live: false,
+                chapters: Vec::new(),
+                chapter: None,
+            })),
+            events_rx: rx_events,
+        };
+        to_ret.inner_thread(tx_events);
+        to_ret
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn player_error_conversions() {
+    use std::io;
+
+    let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "end of stream");
+    assert_eq!(PlayerError::from(eof), PlayerError::EndOfStream);
+
+    let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+    assert!(matches!(PlayerError::from(refused), PlayerError::Io(_)));
+
+    let decode = symphonia::core::errors::Error::DecodeError("invalid frame");
+    assert_eq!(PlayerError::from(decode), PlayerError::Decode("invalid frame".to_string()));
+}
+
+#[test]
+fn plays_local_file_to_memory_sink() {
+    use test_util::{memory_player, temp_path, wait_for, write_wav};
+
+    let path = temp_path("play.wav");
+    write_wav(&path, 8000, 2, 8000, |_| 16384);
+
+    let (mut p, captured) = memory_player(|b| b);
+    p.set_volume(0.5);
+    p.open(path.to_str().unwrap());
+
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.samples.len(), 16000);
+    assert!(captured.samples.iter().all(|&s| s == 0.25));
+    assert_eq!(p.is_playing(), Playing::Finished);
+    assert_eq!(p.buffer_chunks(), vec![(0.0, 1.0)]);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn queue_plays_entries_gapless() {
+    use test_util::{memory_player, temp_path, wait_for, write_wav};
+
+    let first = temp_path("first.wav");
+    let second = temp_path("second.wav");
+    write_wav(&first, 8000, 1, 4000, |_| 1000);
+    write_wav(&second, 8000, 1, 2000, |_| 2000);
+
+    let (mut p, captured) = memory_player(|b| b);
+    p.open(first.to_str().unwrap());
+    p.enqueue(&format!("file://{}", second.to_str().unwrap()));
+
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.outputs_opened, 1);
+    assert_eq!(captured.samples.len(), 6000);
+    assert_eq!(captured.samples[3999], 1000.0 / 32768.0);
+    assert_eq!(captured.samples[4000], 2000.0 / 32768.0);
+    assert_eq!(p.queue_index(), Some(1));
+    assert_eq!(p.queue().len(), 2);
+    let _ = std::fs::remove_file(first);
+    let _ = std::fs::remove_file(second);
+}
+
+#[test]
+fn handles_actions_while_opening() {
+    use test_util::{http_response, http_server, memory_player, wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 8000, |_| 1000);
+    let url = http_server(move |_| {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        http_response(200, &[("content-length", wav.len().to_string())], &wav)
+    });
+
+    let (mut p, _) = memory_player(|b| b);
+    p.open(&format!("{}/slow.wav", url));
+    p.set_volume(0.5);
+    // Handled before the slow host responds.
+    let first = p.events().iter().find(|e| matches!(e, PlayerStatus::Volume(..) | PlayerStatus::Opened(_)));
+    assert_eq!(first, Some(PlayerStatus::Volume(0.5, false)));
+    assert!(wait_for(&p, |e| matches!(e, PlayerStatus::Opened(_))));
+}
+
+#[test]
+fn accurate_seek_starts_at_requested_frame() {
+    use test_util::{play_wav_with, wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 16000, |i| i as i16);
+    let (p, captured) = play_wav_with(wav, |b| b.seek_mode(SeekMode::Accurate));
+    p.seek(1.375);
+
+    assert!(wait_for(&p, |e| matches!(e, PlayerStatus::Seeked(t) if *t == 1.375)));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.samples[0], 11000.0 / 32768.0);
+    assert_eq!(captured.samples.len(), 16000 - 11000);
+}
+
+#[test]
+fn reports_track_metadata() {
+    use test_util::{play_wav_with, wait_for, wav_bytes_tagged};
+
+    let tags = [(*b"INAM", "Episode 1"), (*b"IART", "Host"), (*b"IPRT", "7")];
+    let (p, _) = play_wav_with(wav_bytes_tagged(8000, 1, 800, |_| 0, &tags), |b| b);
+
+    assert!(wait_for(&p, |e| matches!(e, PlayerStatus::Metadata(_))));
+    let metadata = p.metadata().unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("Episode 1"));
+    assert_eq!(metadata.artist.as_deref(), Some("Host"));
+    assert_eq!(metadata.track_number, Some(7));
+}
+
+#[test]
+fn strips_icy_metadata_from_radio_stream() {
+    use test_util::{http_response, http_server, memory_player, wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 8000, |_| 1000);
+    let url = http_server(move |req| {
+        if req.path != "/stream.wav" {
+            return http_response(404, &[], b"");
+        }
+        if req.header("icy-metadata") != Some("1") {
+            return http_response(200, &[], &wav);
+        }
+        let mut body = Vec::new();
+        for (i, audio) in wav.chunks(1000).enumerate() {
+            body.extend_from_slice(audio);
+            let block: &[u8] = match i {
+                0 => b"StreamTitle='Song A';StreamUrl='';",
+                5 => b"StreamTitle='Song B';",
+                _ => b"",
+            };
+            let len = block.len().div_ceil(16);
+            body.push(len as u8);
+            body.extend_from_slice(block);
+            body.resize(body.len() + len * 16 - block.len(), 0);
+        }
+        let headers = [
+            ("icy-metaint", "1000".to_string()),
+            ("icy-name", "Test FM".to_string()),
+            ("icy-br", "128".to_string()),
+        ];
+        http_response(200, &headers, &body)
+    });
+
+    let (mut p, captured) = memory_player(|b| b);
+    p.open(&format!("{}/stream.wav", url));
+
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.samples.len(), 8000);
+    assert!(captured.samples.iter().all(|&s| s == 1000.0 / 32768.0));
+    assert_eq!(p.now_playing().unwrap().title.as_deref(), Some("Song B"));
+    let info = p.stream_info().unwrap();
+    assert_eq!(info.name.as_deref(), Some("Test FM"));
+    assert_eq!(info.bitrate, Some(128));
+}
+
+#[test]
+fn live_stream_is_not_seekable() {
+    use test_util::{http_response, http_server, memory_player, wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 8000, |_| 1000);
+    // No content-length: the body ends when the connection closes.
+    let url = http_server(move |_| http_response(200, &[], &wav));
+
+    let (mut p, captured) = memory_player(|b| b);
+    p.pause();
+    p.open(&format!("{}/live", url));
+    assert!(wait_for(&p, |e| matches!(e, PlayerStatus::Opened(_))));
+    assert!(p.is_live());
+
+    p.seek(0.5);
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Error(PlayerError::NotSeekable)));
+    assert_eq!(p.error(), None);
+
+    p.play();
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    assert_eq!(captured.lock().unwrap().samples.len(), 8000);
+    assert!(p.current_position() > 0.5);
+
+    // Restarting reconnects instead of seeking.
+    p.skip_previous();
+    let timeout = std::time::Duration::from_secs(10);
+    let events: Vec<_> = std::iter::from_fn(|| p.events().recv_timeout(timeout).ok())
+        .take_while(|e| *e != PlayerStatus::Finished)
+        .collect();
+    assert!(events.iter().any(|e| matches!(e, PlayerStatus::Opened(_))));
+    assert!(!events.iter().any(|e| matches!(e, PlayerStatus::Error(_))));
+    assert_eq!(captured.lock().unwrap().samples.len(), 16000);
+}
+
+#[test]
+fn reconnects_after_connection_loss() {
+    use test_util::{fast_reconnect, http_response, http_server, memory_player, range_response};
+    use test_util::{wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 8000, |i| i as i16);
+    let url = http_server(move |req| {
+        if req.header("range").is_some() {
+            return range_response(&wav, req);
+        }
+        // Connection lost after 5000 bytes.
+        let headers = [
+            ("content-length", wav.len().to_string()),
+            ("accept-ranges", "bytes".to_string()),
+        ];
+        http_response(200, &headers, &wav[..5000])
+    });
+
+    let (mut p, captured) = memory_player(|b| b.reconnect(fast_reconnect()));
+    p.open(&format!("{}/episode.wav", url));
+
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Reconnecting(1)));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Reconnected));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    let captured = captured.lock().unwrap();
+    assert_eq!(captured.samples.len(), 8000);
+    assert!(captured.samples.iter().enumerate().all(|(i, &s)| s == i as f32 / 32768.0));
+}
+
+#[test]
+fn buffers_while_source_stalls() {
+    use test_util::{fast_reconnect, http_response, http_server, memory_player, range_response};
+    use test_util::{wait_for, wav_bytes};
+
+    let wav = wav_bytes(8000, 1, 48000, |i| i as i16);
+    let url = http_server(move |req| {
+        if req.header("range").is_some() {
+            // Slow network after the connection loss.
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            return range_response(&wav, req);
+        }
+        let headers = [
+            ("content-length", wav.len().to_string()),
+            ("accept-ranges", "bytes".to_string()),
+        ];
+        http_response(200, &headers, &wav[..70000])
+    });
+
+    let (mut p, captured) = memory_player(|b| b.reconnect(fast_reconnect()).preroll(1.0));
+    p.open(&format!("{}/episode.wav", url));
+
+    assert!(wait_for(&p, |e| *e == PlayerStatus::BufferingStarted));
+    assert!(matches!(p.is_playing(), Playing::Buffering(_)));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::BufferingEnded));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    assert_eq!(captured.lock().unwrap().samples.len(), 48000);
+}
+
+#[test]
+fn navigates_id3_chapters() {
+    use test_util::{play_wav_with, wait_for, wav_bytes};
+
+    let tag = chapters::id3_tag(&[("intro", 0, 1000, "Intro"), ("main", 1000, 2000, "Main")]);
+    let wav = [tag, wav_bytes(8000, 1, 16000, |i| (i / 8000) as i16)].concat();
+    let (p, captured) = play_wav_with(wav, |b| b.seek_mode(SeekMode::Accurate));
+
+    assert!(wait_for(&p, |e| matches!(e, PlayerStatus::Chapters(c) if c.len() == 2)));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::ChapterChanged(Some(0))));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::ChapterChanged(Some(1))));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    assert_eq!(captured.lock().unwrap().samples.len(), 16000);
+    let chapters = p.chapters();
+    assert_eq!(chapters[1].title.as_deref(), Some("Main"));
+    assert_eq!((chapters[1].start, chapters[1].end), (1.0, Some(2.0)));
+    assert_eq!(p.current_chapter(), Some(1));
+
+    // Within 3 seconds of the chapter start, previous goes to the previous chapter.
+    p.previous_chapter();
+    assert!(wait_for(&p, |e| *e == PlayerStatus::ChapterChanged(Some(0))));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+    p.seek_to_chapter(1);
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Seeked(1.0)));
+    assert!(wait_for(&p, |e| *e == PlayerStatus::Finished));
+}
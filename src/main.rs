@@ -18,6 +18,12 @@ fn main() {
                 PlayerStatus::Seeked(pos) => println!("[event] Seeked to: {:.1}s", pos),
                 PlayerStatus::SendPlaying(ref state) => println!("[event] Playing state: {:?}", state),
                 PlayerStatus::Error(ref err) => println!("[event] Error: {}", err),
+                PlayerStatus::Finished => println!("[event] Finished"),
+                PlayerStatus::Reconnecting(attempt) => println!("[event] Reconnecting, attempt {}", attempt),
+                PlayerStatus::Reconnected => println!("[event] Reconnected"),
+                PlayerStatus::NowPlaying(ref np) => println!("[event] Now playing: {:?}", np.title),
+                PlayerStatus::BufferingStarted => println!("[event] Buffering..."),
+                PlayerStatus::BufferingEnded => println!("[event] Buffering done"),
                 _ => {}
             }
         }
@@ -0,0 +1,118 @@
+//! Track metadata (tags and cover art) read by symphonia from ID3v2, Vorbis comments,
+//! MP4 atoms, RIFF INFO and other containers.
+
+use std::sync::Arc;
+
+use symphonia::core::meta::{MetadataRevision, StandardTagKey, StandardVisualKey};
+
+/// Embedded picture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Picture {
+    /// Media (MIME) type, e.g. `image/jpeg`.
+    pub media_type: String,
+    pub data: Arc<[u8]>,
+}
+
+/// Snapshot of the metadata of the current track.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub date: Option<String>,
+    pub genre: Option<String>,
+    pub comment: Option<String>,
+    /// Front cover, or the first picture if none is marked as front cover.
+    pub cover_art: Option<Picture>,
+    /// All tags as (key, value) pairs, keys as in the container (e.g. `TIT2`, `TITLE`).
+    pub tags: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// Update with the tags and pictures of a newer revision. Fields the revision
+    /// doesn't carry are kept.
+    pub(crate) fn merge(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            // RIFF INFO and some ID3 writers keep the C string terminator.
+            let value = tag.value.to_string().trim_end_matches('\0').to_string();
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => Some(&mut self.title),
+                Some(StandardTagKey::Artist) => Some(&mut self.artist),
+                Some(StandardTagKey::Album) => Some(&mut self.album),
+                Some(StandardTagKey::AlbumArtist) => Some(&mut self.album_artist),
+                Some(StandardTagKey::Date) | Some(StandardTagKey::ReleaseDate) => Some(&mut self.date),
+                Some(StandardTagKey::Genre) => Some(&mut self.genre),
+                Some(StandardTagKey::Comment) => Some(&mut self.comment),
+                Some(StandardTagKey::TrackNumber) => {
+                    // ID3 and Vorbis use "3/12" for track 3 of 12.
+                    if let Ok(n) = value.split('/').next().unwrap_or_default().trim().parse() {
+                        self.track_number = Some(n);
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(field) = field {
+                *field = Some(value.clone());
+            }
+
+            match self.tags.iter_mut().find(|(key, _)| *key == tag.key) {
+                Some(entry) => entry.1 = value,
+                None => self.tags.push((tag.key.clone(), value)),
+            }
+        }
+
+        let visuals = revision.visuals();
+        let cover = visuals
+            .iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or(visuals.first());
+        if let Some(cover) = cover {
+            self.cover_art = Some(Picture {
+                media_type: cover.media_type.clone(),
+                data: cover.data.clone().into(),
+            });
+        }
+    }
+}
+
+#[test]
+fn merge_revisions() {
+    use symphonia::core::meta::{MetadataBuilder, Tag, Value, Visual};
+
+    let mut builder = MetadataBuilder::new();
+    builder
+        .add_tag(Tag::new(Some(StandardTagKey::TrackTitle), "TIT2", Value::from("First")))
+        .add_tag(Tag::new(Some(StandardTagKey::TrackNumber), "TRCK", Value::from("3/12")))
+        .add_tag(Tag::new(None, "TXXX:mood", Value::from("calm")))
+        .add_visual(Visual {
+            media_type: "image/png".to_string(),
+            dimensions: None,
+            bits_per_pixel: None,
+            color_mode: None,
+            usage: Some(StandardVisualKey::FrontCover),
+            tags: vec![],
+            data: Box::new([1, 2, 3]),
+        });
+    let mut meta = Metadata::default();
+    meta.merge(&builder.metadata());
+
+    let mut builder = MetadataBuilder::new();
+    builder.add_tag(Tag::new(Some(StandardTagKey::TrackTitle), "TIT2", Value::from("Second")));
+    meta.merge(&builder.metadata());
+
+    assert_eq!(meta.title.as_deref(), Some("Second"));
+    assert_eq!(meta.track_number, Some(3));
+    assert_eq!(meta.artist, None);
+    assert_eq!(meta.cover_art.unwrap().data[..], [1, 2, 3]);
+    assert_eq!(
+        meta.tags,
+        vec![
+            ("TIT2".to_string(), "Second".to_string()),
+            ("TRCK".to_string(), "3/12".to_string()),
+            ("TXXX:mood".to_string(), "calm".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,146 @@
+//! Minimal MPEG transport stream demuxer extracting the elementary stream of the first audio
+//! track (ADTS AAC or MPEG audio), used for HLS segments.
+
+use std::io;
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+/// Stream types of the PMT with a codec symphonia decodes from the elementary stream.
+const STREAM_TYPE_MPEG1_AUDIO: u8 = 0x03;
+const STREAM_TYPE_MPEG2_AUDIO: u8 = 0x04;
+const STREAM_TYPE_ADTS_AAC: u8 = 0x0f;
+
+/// Demuxer state kept across the segments of one stream.
+#[derive(Default)]
+pub(crate) struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+}
+
+/// Whether `data` starts like a transport stream.
+pub(crate) fn is_transport_stream(data: &[u8]) -> bool {
+    data.first() == Some(&SYNC_BYTE) && data.get(PACKET_SIZE).is_none_or(|&b| b == SYNC_BYTE)
+}
+
+impl TsDemuxer {
+    /// Append the audio payload of the packets in `data` to `out`.
+    pub fn demux(&mut self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        for packet in data.chunks(PACKET_SIZE) {
+            if packet.len() < PACKET_SIZE || packet[0] != SYNC_BYTE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "mpegts: lost sync"));
+            }
+            let start = packet[1] & 0x40 != 0;
+            let pid = u16::from_be_bytes([packet[1] & 0x1f, packet[2]]);
+            let control = (packet[3] >> 4) & 0x3;
+            if control & 0x1 == 0 {
+                continue; // No payload.
+            }
+            let offset = if control & 0x2 != 0 { 5 + packet[4] as usize } else { 4 };
+            let Some(payload) = packet.get(offset..) else {
+                continue;
+            };
+
+            if pid == 0 && start {
+                self.pmt_pid = parse_pat(payload);
+            } else if Some(pid) == self.pmt_pid && start {
+                if self.audio_pid.is_none() {
+                    self.audio_pid = parse_pmt(payload);
+                }
+            } else if self.audio_pid == Some(pid) {
+                out.extend_from_slice(if start { pes_payload(payload) } else { payload });
+            }
+        }
+        if self.pmt_pid.is_some() && self.audio_pid.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "mpegts: no supported audio stream"));
+        }
+        Ok(())
+    }
+}
+
+/// Section of a PSI table starting after the pointer field, without the CRC.
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let section = payload.get(1 + *payload.first()? as usize..)?;
+    let len = (u16::from_be_bytes([*section.get(1)?, *section.get(2)?]) & 0xfff) as usize;
+    section.get(..(3 + len).checked_sub(4)?)
+}
+
+/// PID of the first program's PMT.
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    section(payload)?
+        .get(8..)?
+        .chunks_exact(4)
+        .find(|entry| entry[0] != 0 || entry[1] != 0)
+        .map(|entry| u16::from_be_bytes([entry[2] & 0x1f, entry[3]]))
+}
+
+/// PID of the first supported audio stream.
+fn parse_pmt(payload: &[u8]) -> Option<u16> {
+    let section = section(payload)?;
+    let info_len = (u16::from_be_bytes([*section.get(10)?, *section.get(11)?]) & 0xfff) as usize;
+    let mut streams = section.get(12 + info_len..)?;
+    while streams.len() >= 5 {
+        let stream_type = streams[0];
+        let pid = u16::from_be_bytes([streams[1] & 0x1f, streams[2]]);
+        let es_info_len = (u16::from_be_bytes([streams[3], streams[4]]) & 0xfff) as usize;
+        if matches!(stream_type, STREAM_TYPE_ADTS_AAC | STREAM_TYPE_MPEG1_AUDIO | STREAM_TYPE_MPEG2_AUDIO) {
+            return Some(pid);
+        }
+        streams = streams.get(5 + es_info_len..)?;
+    }
+    None
+}
+
+/// Data of a packet starting a PES packet, after the PES header.
+fn pes_payload(payload: &[u8]) -> &[u8] {
+    if payload.len() < 9 || payload[..3] != [0, 0, 1] {
+        return &[];
+    }
+    payload.get(9 + payload[8] as usize..).unwrap_or(&[])
+}
+
+/// Transport stream carrying `es` as one PES packet of an ADTS audio stream.
+#[cfg(test)]
+fn mux_audio(es: &[u8]) -> Vec<u8> {
+    fn packet(pid: u16, start: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![SYNC_BYTE, (pid >> 8) as u8 | if start { 0x40 } else { 0 }, pid as u8];
+        let stuffing = PACKET_SIZE - 4 - payload.len();
+        if stuffing > 0 {
+            // Adaptation field filling the packet.
+            packet.push(0x30);
+            packet.push(stuffing as u8 - 1);
+            if stuffing > 1 {
+                packet.push(0);
+                packet.resize(packet.len() + stuffing - 2, 0xff);
+            }
+        } else {
+            packet.push(0x10);
+        }
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    let pat = [0, 0x00, 0xb0, 13, 0, 1, 0xc1, 0, 0, 0, 1, 0xe1, 0x00, 0, 0, 0, 0];
+    let pmt = [0, 0x02, 0xb0, 18, 0, 1, 0xc1, 0, 0, 0xe1, 0x01, 0xf0, 0, STREAM_TYPE_ADTS_AAC, 0xe1, 0x01, 0xf0, 0, 0, 0, 0, 0];
+    let mut ts = packet(0, true, &pat);
+    ts.extend(packet(0x100, true, &pmt));
+    let mut pes = vec![0, 0, 1, 0xc0, 0, 0, 0x80, 0, 0];
+    pes.extend_from_slice(es);
+    for (i, payload) in pes.chunks(PACKET_SIZE - 4).enumerate() {
+        ts.extend(packet(0x101, i == 0, payload));
+    }
+    ts
+}
+
+#[test]
+fn demuxes_audio_elementary_stream() {
+    let es: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let ts = mux_audio(&es);
+    assert!(is_transport_stream(&ts));
+    assert!(!is_transport_stream(b"RIFF"));
+
+    let mut demuxer = TsDemuxer::default();
+    let mut out = Vec::new();
+    demuxer.demux(&ts, &mut out).unwrap();
+    assert_eq!(out, es);
+}
@@ -0,0 +1,226 @@
+//! Audio outputs (sinks) the player writes decoded audio to.
+//!
+//! By default `Player` plays on the system audio device (`playback` feature). Any other sink implementing
+//! `AudioSink` can be set with `PlayerBuilder::sink`, e.g. `NullSink` on machines without
+//! a sound card or `MemorySink` to inspect decoded audio in tests.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec};
+use symphonia::core::units::Duration;
+
+#[cfg(feature = "playback")]
+pub use crate::cpalaudio::CpalSink;
+use crate::volume::GainRamp;
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum AudioOutputError {
+    #[error("failed to open output stream")]
+    OpenStreamError,
+    #[error("failed to start output stream")]
+    PlayStreamError,
+    #[error("output stream closed: {0}")]
+    StreamClosedError(String),
+    #[error("output device not found: {0}")]
+    DeviceNotFoundError(String),
+}
+
+impl From<AudioOutputError> for crate::PlayerError {
+    fn from(e: AudioOutputError) -> Self {
+        crate::PlayerError::AudioDeviceUnavailable(e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, AudioOutputError>;
+
+/// Identifies an output device by audio host (backend) and device name.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DeviceId {
+    pub host: String,
+    pub name: String,
+}
+
+/// Output opened for one signal spec. Used only from the player's engine thread.
+pub trait AudioOutput {
+    /// Write decoded audio. May block to apply backpressure (e.g. real time playback).
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
+    /// Write any remaining buffered audio and wait until it is played out.
+    fn flush(&mut self);
+    /// Set linear output gain (0.0 - 1.0). Changes are ramped to avoid clicks.
+    fn set_volume(&mut self, volume: f32);
+    /// Whether the output ran out of audio since the last call, e.g. because the player
+    /// waited for the network. Outputs without a playback clock never underrun.
+    fn take_underrun(&mut self) -> bool {
+        false
+    }
+}
+
+/// Factory of audio outputs. The player opens an output when it decodes the first packet
+/// of a stream, and opens a new one when the signal spec changes.
+pub trait AudioSink: Send + Sync {
+    /// Open output for audio with `spec`. `duration` is the max number of frames per
+    /// `AudioOutput::write`, `volume` the initial gain.
+    fn open(&mut self, spec: SignalSpec, duration: Duration, volume: f32) -> Result<Box<dyn AudioOutput>>;
+
+    /// Select the device for outputs opened from now on. Sinks without devices ignore it.
+    fn set_device(&mut self, _device: Option<DeviceId>) {}
+}
+
+/// Sink which discards audio. Useful on servers and CI machines without a sound card.
+#[derive(Clone, Debug)]
+pub struct NullSink {
+    realtime: bool,
+}
+
+impl NullSink {
+    /// Consume audio at playback speed, so position and events advance as with a device.
+    pub fn realtime() -> Self {
+        NullSink { realtime: true }
+    }
+
+    /// Consume audio as fast as it is decoded.
+    pub fn fast() -> Self {
+        NullSink { realtime: false }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn open(&mut self, spec: SignalSpec, _duration: Duration, _volume: f32) -> Result<Box<dyn AudioOutput>> {
+        Ok(Box::new(NullOutput {
+            realtime: self.realtime,
+            rate: spec.rate,
+            clock: None,
+        }))
+    }
+}
+
+struct NullOutput {
+    realtime: bool,
+    rate: u32,
+    /// Start of real time playback and number of frames written since.
+    clock: Option<(Instant, u64)>,
+}
+
+/// Written audio more than this behind the clock (e.g. after pause) restarts the clock.
+const NULL_OUTPUT_MAX_LAG: StdDuration = StdDuration::from_millis(500);
+
+impl AudioOutput for NullOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        if !self.realtime || self.rate == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let (start, frames) = match self.clock {
+            Some((start, frames))
+                if now.duration_since(start)
+                    < StdDuration::from_secs_f64(frames as f64 / self.rate as f64) + NULL_OUTPUT_MAX_LAG =>
+            {
+                (start, frames)
+            }
+            _ => (now, 0),
+        };
+
+        let frames = frames + decoded.frames() as u64;
+        self.clock = Some((start, frames));
+
+        let played_until = start + StdDuration::from_secs_f64(frames as f64 / self.rate as f64);
+        if let Some(ahead) = played_until.checked_duration_since(now) {
+            std::thread::sleep(ahead);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {}
+
+    fn set_volume(&mut self, _volume: f32) {}
+}
+
+/// Audio captured by `MemorySink`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CapturedAudio {
+    /// Spec of the last opened output.
+    pub spec: Option<SignalSpec>,
+    /// Interleaved samples with volume applied.
+    pub samples: Vec<f32>,
+    /// Number of outputs opened, e.g. 1 for gapless playback of a queue.
+    pub outputs_opened: usize,
+}
+
+/// Sink which stores decoded audio as interleaved `f32` samples in memory.
+/// Audio is consumed as fast as it is decoded.
+///
+/// ```no_run
+/// use url2audio::{output::MemorySink, Player};
+///
+/// let sink = MemorySink::new();
+/// let captured = sink.captured();
+/// let mut player = Player::builder().sink(sink).build();
+/// player.open("/tmp/test.wav");
+/// // ... wait for `PlayerStatus::Finished`
+/// println!("{} samples", captured.lock().unwrap().samples.len());
+/// ```
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    captured: Arc<Mutex<CapturedAudio>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Shared handle to the captured audio.
+    pub fn captured(&self) -> Arc<Mutex<CapturedAudio>> {
+        self.captured.clone()
+    }
+}
+
+impl AudioSink for MemorySink {
+    fn open(&mut self, spec: SignalSpec, duration: Duration, volume: f32) -> Result<Box<dyn AudioOutput>> {
+        {
+            let mut captured = self.captured.lock().unwrap();
+            captured.spec = Some(spec);
+            captured.outputs_opened += 1;
+        }
+        Ok(Box::new(MemoryOutput {
+            captured: self.captured.clone(),
+            sample_buf: SampleBuffer::new(duration, spec),
+            channels: spec.channels.count(),
+            volume,
+            ramp: GainRamp::new(volume, spec.rate),
+        }))
+    }
+}
+
+struct MemoryOutput {
+    captured: Arc<Mutex<CapturedAudio>>,
+    sample_buf: SampleBuffer<f32>,
+    channels: usize,
+    volume: f32,
+    ramp: GainRamp,
+}
+
+impl AudioOutput for MemoryOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        if decoded.frames() == 0 {
+            return Ok(());
+        }
+        self.sample_buf.copy_interleaved_ref(decoded);
+
+        let mut captured = self.captured.lock().unwrap();
+        let start = captured.samples.len();
+        captured.samples.extend_from_slice(self.sample_buf.samples());
+        self.ramp
+            .apply(self.volume, &mut captured.samples[start..], self.channels);
+        Ok(())
+    }
+
+    fn flush(&mut self) {}
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+}
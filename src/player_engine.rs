@@ -1,21 +1,35 @@
-use crossbeam_channel::{Receiver, Sender};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{select, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use symphonia::core::codecs::{Decoder, CODEC_TYPE_NULL};
 use symphonia::core::units::TimeBase;
 use symphonia::core::{
-    audio::SignalSpec,
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
     codecs::DecoderOptions,
     errors::{Error, Result},
     formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track},
-    io::MediaSourceStream,
+    io::{MediaSource, MediaSourceStream},
     meta::MetadataOptions,
-    probe::Hint,
+    probe::{Hint, ProbeResult},
     units::{Duration, Time},
 };
 
-use crate::cpalaudio;
 use crate::{
-    cpalaudio::{AudioOutput, CpalAudioOutput},
-    url_source_buff::UrlSourceBuf,
+    chapters::{chapter_at, read_chapters, Chapter},
+    dash::{self, is_dash},
+    hls::{self, is_hls},
+    http::SourceOptions,
+    icy::{NowPlaying, StreamInfo},
+    metadata::Metadata,
+    output::{AudioOutput, AudioSink, DeviceId},
+    playlist::{is_hls_playlist, is_playlist, is_playlist_type, parse_playlist},
+    queue::{PlayQueue, Removed},
+    segments::manifest_url,
+    url_source_buff::{Prefetch, UrlSourceBuf},
+    PlayerError, Url2AudioError,
 };
 
 #[derive(PartialEq, Clone, Debug)]
@@ -25,6 +39,24 @@ pub enum PlayerActions {
     Seek(f64),
     Close,
     Open(String),
+    Enqueue(String),
+    /// Insert source at queue index.
+    Insert(usize, String),
+    /// Remove queue entry at index.
+    Remove(usize),
+    ClearQueue,
+    Next,
+    Previous,
+    JumpTo(usize),
+    NextChapter,
+    PreviousChapter,
+    /// Seek to the start of the chapter at index.
+    SeekToChapter(usize),
+    /// Linear volume, clamped to 0.0 - 1.0.
+    SetVolume(f32),
+    SetMute(bool),
+    /// Output device to play on, `None` for the system default.
+    SetOutputDevice(Option<DeviceId>),
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -33,19 +65,94 @@ pub enum PlayerStatus {
     /// (position, duration)
     SendTimeStats(f64, f64),
     ChunkAdded(f32, f32),
-    Error(String),
+    Error(PlayerError),
     ClearError,
     Opened(String),
     Closed,
     Seeked(f64),
+    /// End of stream reached and all buffered audio has been played.
+    Finished,
+    /// Queue entries and index of the current entry.
+    QueueChanged(Vec<String>, Option<usize>),
+    /// (volume, muted)
+    Volume(f32, bool),
+    OutputDevice(Option<DeviceId>),
+    /// Metadata of the current track, sent when it is opened and when the stream carries
+    /// new tags.
+    Metadata(Metadata),
+    /// Is the opened source a live stream. Sent before `Opened`.
+    Live(bool),
+    /// Network failure of the current source, reconnect attempt (starting at 1) follows.
+    Reconnecting(u32),
+    /// Source reconnected after `Reconnecting`, playback continues.
+    Reconnected,
+    /// Station info from the ICY headers of an internet radio stream.
+    StreamInfo(StreamInfo),
+    /// Song change announced in the ICY metadata of an internet radio stream.
+    NowPlaying(NowPlaying),
+    /// Playback waits for the network: the source can't deliver data, the audio output ran
+    /// out of audio, or the preroll after open, seek or resume isn't downloaded yet.
+    BufferingStarted,
+    /// Downloaded part of the preroll (0.0 - 1.0) while buffering.
+    Buffering(f32),
+    /// Preroll downloaded, playback continues.
+    BufferingEnded,
+    /// Chapters of the current track, sent when it is opened (empty if it has none).
+    Chapters(Vec<Chapter>),
+    /// Playback entered the chapter at index, `None` before the first chapter.
+    ChapterChanged(Option<usize>),
 }
 
 pub struct PlayerEngine {
     reader: Option<Box<dyn FormatReader>>,
+    decoder: Option<Box<dyn Decoder>>,
     rx: Receiver<PlayerActions>,
     tx_status: Sender<PlayerStatus>,
     src: Option<String>,
-    error: Option<String>,
+    error: Option<PlayerError>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    /// End timestamp of the track (`start_ts + n_frames`), if known.
+    n_frames: Option<u64>,
+    /// Current source is a live stream, position is counted from played frames.
+    live: bool,
+    frames_played: u64,
+    playing: bool,
+    /// End of stream reached and all buffered audio played out.
+    finished: bool,
+    pending_seek: Option<f64>,
+    pub(crate) seek_mode: SeekMode,
+    pub(crate) source_options: SourceOptions,
+    /// Seconds of audio downloaded ahead before playback starts or continues after buffering.
+    pub(crate) preroll: f64,
+    /// Download state of the current HTTP source, `None` for local files.
+    prefetch: Option<Prefetch>,
+    /// Last reported fill while buffering.
+    buffering: Option<f32>,
+    /// After an accurate seek, timestamp up to which decoded audio is discarded.
+    skip_until: Option<u64>,
+    /// Last reported playback position in seconds.
+    position: f64,
+    queue: PlayQueue,
+    /// Next queue entry, opened in background before the current one ends.
+    preload: Option<Preload>,
+    /// Current queue entry, while it is opened in background.
+    loading: Option<Preload>,
+    metadata: Metadata,
+    chapters: Vec<Chapter>,
+    /// Index of the chapter at the last reported position.
+    chapter: Option<usize>,
+    /// Chapter the pending seek goes to.
+    chapter_seek: Option<usize>,
+    /// Chapter a chapter seek landed just before the start of. It is current until playback
+    /// reaches its start.
+    landed_chapter: Option<usize>,
+    /// Signal spec and capacity the audio output was opened with.
+    output_format: Option<(SignalSpec, Duration)>,
+    volume: f32,
+    muted: bool,
+    output_device: Option<DeviceId>,
+    pub(crate) sink: Box<dyn AudioSink>,
     drop_initiated: bool,
 }
 
@@ -53,7 +160,9 @@ pub struct PlayerEngine {
 pub enum Playing {
     Playing,
     Paused,
-    Finished
+    Finished,
+    /// Waiting for the network, with the downloaded part of the preroll (0.0 - 1.0).
+    Buffering(f32),
 }
 
 pub struct PlayerState {
@@ -61,16 +170,99 @@ pub struct PlayerState {
     pub duration: f64,
     pub position: f64,
     pub pending_seek: Option<f64>,
-    pub error: Option<String>,
+    pub error: Option<PlayerError>,
     pub chunks: Vec<(f32, f32)>,
+    pub queue: Vec<String>,
+    pub queue_index: Option<usize>,
+    pub volume: f32,
+    pub muted: bool,
+    pub output_device: Option<DeviceId>,
+    pub metadata: Option<Metadata>,
+    pub stream_info: Option<StreamInfo>,
+    pub now_playing: Option<NowPlaying>,
+    pub live: bool,
+    pub chapters: Vec<Chapter>,
+    pub chapter: Option<usize>,
 }
 
 enum ActionResult {
     Break,
-    Continue,
     Handled,
 }
 
+/// Opened source ready for decoding.
+pub(crate) struct LoadedTrack {
+    pub src: String,
+    pub reader: Box<dyn FormatReader>,
+    pub decoder: Box<dyn Decoder>,
+    pub track_id: u32,
+    pub time_base: Option<TimeBase>,
+    pub n_frames: Option<u64>,
+    /// Source is not seekable and unbounded.
+    pub live: bool,
+    pub metadata: Metadata,
+    /// Chapters ordered by start.
+    pub chapters: Vec<Chapter>,
+    /// Download state of an HTTP source.
+    pub prefetch: Option<Prefetch>,
+    /// Status link of the source, `None` when opened without a player.
+    pub link: Option<StatusLink>,
+}
+
+/// Queue entry being opened in background.
+struct Preload {
+    src: String,
+    rx: Receiver<std::result::Result<LoadedTrack, PlayerError>>,
+}
+
+/// Status sender for a source which may be created before it becomes the current one
+/// (preloaded queue entry). Statuses sent while detached are kept and delivered on `connect`,
+/// so buffer reports of the next entry are not mixed with the ones of the playing entry.
+#[derive(Clone, Default)]
+pub(crate) struct StatusLink(Arc<Mutex<LinkState>>);
+
+#[derive(Default)]
+struct LinkState {
+    tx: Option<Sender<PlayerStatus>>,
+    pending: Vec<PlayerStatus>,
+}
+
+impl StatusLink {
+    pub fn send(&self, status: PlayerStatus) {
+        let mut state = self.0.lock().unwrap();
+        match state.tx.as_ref() {
+            Some(tx) => {
+                let _ = tx.try_send(status);
+            }
+            None => state.pending.push(status),
+        }
+    }
+
+    pub fn connect(&self, tx: Sender<PlayerStatus>) {
+        let mut state = self.0.lock().unwrap();
+        for status in state.pending.drain(..) {
+            let _ = tx.try_send(status);
+        }
+        state.tx = Some(tx);
+    }
+}
+
+/// Seconds before the end of the current entry at which the next queue entry is opened.
+/// Entries of unknown duration have no preload, the next entry is opened when they end.
+const PRELOAD_SECS: f64 = 15.0;
+/// `Previous` restarts the current entry instead if it played longer than this.
+const PREVIOUS_RESTART_SECS: f64 = 3.0;
+/// Coarse seeks to a chapter may land this much before its start.
+const CHAPTER_TOLERANCE_SECS: f64 = 0.25;
+/// Playlists in playlists are followed this deep.
+const MAX_PLAYLIST_DEPTH: usize = 2;
+/// Longest playlist file read.
+const MAX_PLAYLIST_LEN: u64 = 1 << 20;
+/// Default `preroll`.
+const DEFAULT_PREROLL_SECS: f64 = 2.0;
+/// Interval of buffering progress checks.
+const BUFFERING_POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
 impl PlayerEngine {
     pub fn new(
         rx: Receiver<PlayerActions>,
@@ -78,10 +270,39 @@ impl PlayerEngine {
     ) -> Self {
         Self {
             reader: None,
+            decoder: None,
             rx,
             tx_status,
             src: None,
             error: None,
+            track_id: 0,
+            time_base: None,
+            n_frames: None,
+            live: false,
+            frames_played: 0,
+            playing: true,
+            finished: false,
+            pending_seek: None,
+            seek_mode: SeekMode::Coarse,
+            source_options: Default::default(),
+            preroll: DEFAULT_PREROLL_SECS,
+            prefetch: None,
+            buffering: None,
+            skip_until: None,
+            position: 0.0,
+            queue: Default::default(),
+            preload: None,
+            loading: None,
+            metadata: Default::default(),
+            chapters: Vec::new(),
+            chapter: None,
+            chapter_seek: None,
+            landed_chapter: None,
+            output_format: None,
+            volume: 1.0,
+            muted: false,
+            output_device: None,
+            sink: default_sink(),
             drop_initiated: false
         }
     }
@@ -89,8 +310,6 @@ impl PlayerEngine {
     fn handle_action(
         &mut self,
         action: &PlayerActions,
-        playing: &mut bool,
-        decoder: &mut Option<Box<dyn Decoder>>,
         audio_output: &mut Option<Box<dyn AudioOutput>>,
     ) -> ActionResult {
         match action {
@@ -99,346 +318,621 @@ impl PlayerEngine {
                 ActionResult::Break
             }
             PlayerActions::Open(src) => {
-                self.error = None;
-                *decoder = None;
-                *audio_output = None;
-                let _ = self.tx_status.send(PlayerStatus::ClearError);
-                let _res = self.open(src);
-                if self.error.is_none() {
-                    let _ = self.tx_status.send(PlayerStatus::Opened(src.clone()));
-                }
-                ActionResult::Continue
+                self.queue.replace(src);
+                self.play_index(0, false, audio_output);
+                ActionResult::Handled
             }
             PlayerActions::Pause => {
-                *playing = false;
+                self.playing = false;
+                self.end_buffering();
                 let _ = self.tx_status.send(PlayerStatus::SendPlaying(Playing::Paused));
                 ActionResult::Handled
             }
             PlayerActions::Resume => {
-                *playing = true;
+                self.playing = true;
+                if self.finished {
+                    match self.queue.current() {
+                        // Live streams can't seek, reconnect instead.
+                        Some(index) if self.live => self.play_index(index, false, audio_output),
+                        // Play again from the beginning.
+                        _ => self.pending_seek = Some(0.0),
+                    }
+                }
                 let _ = self.tx_status.send(PlayerStatus::SendPlaying(Playing::Playing));
+                if let Some(output) = audio_output {
+                    // The output ran empty while paused.
+                    output.take_underrun();
+                }
+                self.check_preroll();
+                ActionResult::Handled
+            }
+            PlayerActions::Seek(_)
+            | PlayerActions::NextChapter
+            | PlayerActions::PreviousChapter
+            | PlayerActions::SeekToChapter(_)
+                if self.live =>
+            {
+                let _ = self.tx_status.send(PlayerStatus::Error(PlayerError::NotSeekable));
                 ActionResult::Handled
             }
-            PlayerActions::Seek(_) => ActionResult::Handled,
+            PlayerActions::Seek(t) => {
+                self.pending_seek = Some(*t);
+                self.chapter_seek = None;
+                ActionResult::Handled
+            }
+            PlayerActions::Enqueue(src) => {
+                let index = self.queue.push(src);
+                self.entry_added(index, audio_output);
+                ActionResult::Handled
+            }
+            PlayerActions::Insert(index, src) => {
+                let index = self.queue.insert(*index, src);
+                self.entry_added(index, audio_output);
+                ActionResult::Handled
+            }
+            PlayerActions::Remove(index) => {
+                match self.queue.remove(*index) {
+                    Removed::None => {}
+                    Removed::Other => {
+                        // Preloaded entry may not be the next one anymore.
+                        self.preload = None;
+                        self.send_queue();
+                    }
+                    Removed::Current => {
+                        // Entry which took the place of the removed one is played instead.
+                        if self.queue.get(*index).is_some() {
+                            self.play_index(*index, false, audio_output);
+                        } else {
+                            self.stop(audio_output);
+                        }
+                    }
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::ClearQueue => {
+                self.queue.clear();
+                self.stop(audio_output);
+                ActionResult::Handled
+            }
+            PlayerActions::Next => {
+                if let Some(index) = self.queue.next_index() {
+                    self.play_index(index, false, audio_output);
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::Previous => {
+                match self.queue.previous_index() {
+                    Some(index) if self.position <= PREVIOUS_RESTART_SECS => {
+                        self.play_index(index, false, audio_output);
+                    }
+                    // Live streams can't seek, reconnect instead.
+                    _ if self.live => {
+                        if let Some(index) = self.queue.current() {
+                            self.play_index(index, false, audio_output);
+                        }
+                    }
+                    _ => self.pending_seek = Some(0.0),
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::JumpTo(index) => {
+                if self.queue.get(*index).is_some() {
+                    self.play_index(*index, false, audio_output);
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::NextChapter => {
+                let next = self.chapter.map_or(0, |c| c + 1);
+                if let Some(chapter) = self.chapters.get(next).filter(|c| c.start > self.position) {
+                    self.pending_seek = Some(chapter.start);
+                    self.chapter_seek = Some(next);
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::PreviousChapter => {
+                // Restart the current chapter, unless it just started.
+                let target = match self.chapter {
+                    Some(c) if self.position - self.chapters[c].start > PREVIOUS_RESTART_SECS => Some(c),
+                    Some(c) => Some(c.saturating_sub(1)),
+                    None => None,
+                };
+                if let Some(index) = target.filter(|&c| c < self.chapters.len()) {
+                    self.pending_seek = Some(self.chapters[index].start);
+                    self.chapter_seek = Some(index);
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::SeekToChapter(index) => {
+                if let Some(chapter) = self.chapters.get(*index) {
+                    self.pending_seek = Some(chapter.start);
+                    self.chapter_seek = Some(*index);
+                }
+                ActionResult::Handled
+            }
+            PlayerActions::SetVolume(volume) => {
+                self.volume = volume.clamp(0.0, 1.0);
+                self.volume_changed(audio_output);
+                ActionResult::Handled
+            }
+            PlayerActions::SetMute(muted) => {
+                self.muted = *muted;
+                self.volume_changed(audio_output);
+                ActionResult::Handled
+            }
+            PlayerActions::SetOutputDevice(device) => {
+                self.output_device = device.clone();
+                self.sink.set_device(device.clone());
+                // Play out what is buffered, the output is reopened on the new device
+                // with the next packet, continuing from the current position.
+                if let Some(mut output) = audio_output.take() {
+                    output.flush();
+                }
+                if matches!(self.error, Some(PlayerError::AudioDeviceUnavailable(_))) {
+                    self.error = None;
+                }
+                let _ = self.tx_status.send(PlayerStatus::OutputDevice(device.clone()));
+                ActionResult::Handled
+            }
+        }
+    }
+
+    /// Handle all queued actions without blocking.
+    fn process_actions(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) -> ActionResult {
+        while let Ok(a) = self.rx.try_recv() {
+            if let ActionResult::Break = self.handle_action(&a, audio_output) {
+                return ActionResult::Break;
+            }
+        }
+        ActionResult::Handled
+    }
+
+    /// Enter error state and report `err` to the player.
+    fn set_error(&mut self, err: PlayerError) {
+        self.end_buffering();
+        self.error = Some(err.clone());
+        let _ = self.tx_status.send(PlayerStatus::Error(err));
+    }
+
+    /// Gain applied to the audio output.
+    fn output_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    fn volume_changed(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        if let Some(output) = audio_output {
+            output.set_volume(self.output_volume());
+        }
+        let _ = self.tx_status.send(PlayerStatus::Volume(self.volume, self.muted));
+    }
+
+    fn send_queue(&self) {
+        let _ = self.tx_status.send(PlayerStatus::QueueChanged(
+            self.queue.items().to_vec(),
+            self.queue.current(),
+        ));
+    }
+
+    /// Queue entry was added at `index`.
+    fn entry_added(&mut self, index: usize, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        // Preloaded entry may not be the next one anymore.
+        self.preload = None;
+        // Nothing is playing or the queue ran out: continue with the new entry.
+        if self.queue.current().is_none() || (self.finished && self.queue.next_index() == Some(index)) {
+            self.play_index(index, false, audio_output);
+        } else {
+            self.send_queue();
+        }
+    }
+
+    /// Start playing queue entry at `index`. With `gapless` the audio output is kept
+    /// and the preloaded entry is used if available.
+    fn play_index(&mut self, index: usize, gapless: bool, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        let Some(src) = self.queue.get(index).map(|s| s.to_string()) else {
+            return;
+        };
+        self.queue.set_current(index);
+
+        let preload = self.preload.take().filter(|p| p.src == src);
+        if !gapless {
+            *audio_output = None;
+        }
+        self.end_buffering();
+        self.reader = None;
+        self.decoder = None;
+        self.prefetch = None;
+        self.src = None;
+        self.error = None;
+        self.finished = false;
+        self.pending_seek = None;
+        self.skip_until = None;
+        self.position = 0.0;
+        self.chapters = Vec::new();
+        self.chapter = None;
+        self.chapter_seek = None;
+        self.landed_chapter = None;
+        self.live = false;
+        let _ = self.tx_status.send(PlayerStatus::ClearError);
+        self.send_queue();
+
+        // Opening may take a while on slow hosts, it is done in background so actions are
+        // handled meanwhile. A preload which is ready already continues without a gap.
+        let loading = preload.unwrap_or_else(|| self.load_in_background(src));
+        match loading.rx.try_recv() {
+            Ok(loaded) => self.track_loaded(loading.src, loaded),
+            Err(TryRecvError::Empty) => self.loading = Some(loading),
+            Err(TryRecvError::Disconnected) => self.loading = Some(self.load_in_background(loading.src)),
+        }
+    }
+
+    /// Make the loaded track of entry `src` the current one.
+    fn track_loaded(&mut self, src: String, loaded: std::result::Result<LoadedTrack, PlayerError>) {
+        match loaded {
+            Ok(track) => {
+                if let Some(link) = &track.link {
+                    link.connect(self.tx_status.clone());
+                }
+                self.reader = Some(track.reader);
+                self.decoder = Some(track.decoder);
+                self.track_id = track.track_id;
+                self.time_base = track.time_base;
+                self.n_frames = track.n_frames;
+                self.src = Some(track.src);
+                self.metadata = track.metadata;
+                self.chapters = track.chapters;
+                self.prefetch = track.prefetch;
+                self.live = track.live;
+                self.frames_played = 0;
+                let _ = self.tx_status.send(PlayerStatus::Live(self.live));
+                let _ = self.tx_status.send(PlayerStatus::Opened(src));
+                let _ = self.tx_status.send(PlayerStatus::Metadata(self.metadata.clone()));
+                let _ = self.tx_status.send(PlayerStatus::Chapters(self.chapters.clone()));
+                self.update_chapter();
+                self.check_preroll();
+            }
+            Err(e) => self.set_error(e),
         }
     }
 
+    /// Stop playback of the current entry, leaving the player idle.
+    fn stop(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        self.end_buffering();
+        *audio_output = None;
+        self.reader = None;
+        self.decoder = None;
+        self.prefetch = None;
+        self.src = None;
+        self.preload = None;
+        self.loading = None;
+        self.pending_seek = None;
+        self.skip_until = None;
+        self.send_queue();
+    }
+
+    /// Open the next queue entry in background.
+    fn start_preload(&mut self) {
+        let Some(src) = self.queue.next_index().and_then(|i| self.queue.get(i)) else {
+            return;
+        };
+        self.preload = Some(self.load_in_background(src.to_string()));
+    }
+
+    fn load_in_background(&self, src: String) -> Preload {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let thread_src = src.clone();
+        let options = self.source_options.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(load_track(&thread_src, Some(StatusLink::default()), &options));
+        });
+        Preload { src, rx }
+    }
+
     pub fn start(&mut self) -> Result<i32> {
-        let mut playing = true;
-        let mut track_id: u32 = 0;
-        let mut track: Option<&Track>;
-        let mut tb: Option<TimeBase> = None;
-        let mut dur: Option<u64> = None;
-        let mut decoder: Option<Box<dyn Decoder>> = None;
         let mut audio_output = None;
-        let decode_opts: DecoderOptions = Default::default();
-        let result = 'main: loop {
-
+        loop {
             if self.drop_initiated {
-                break Ok(0);
+                break;
             }
 
-            let action = match self.rx.try_recv() {
-                Ok(a) => Some(a),
-                Err(_e) => None,
-            };
+            if let ActionResult::Break = self.process_actions(&mut audio_output) {
+                break;
+            }
 
-            if let Some(ref a) = action {
-                match self.handle_action(a, &mut playing, &mut decoder, &mut audio_output) {
-                    ActionResult::Break => break Ok(0),
-                    ActionResult::Continue => {
-                        // Open was handled, now set up decoder for the new stream
-                        let track_num: Option<usize> = None;
-
-                        if let Some(reader) = self.reader.as_mut() {
-                            track = track_num
-                                .and_then(|t| reader.tracks().get(t))
-                                .or_else(|| first_supported_track(reader.tracks()));
-
-                            track_id = track.unwrap().id;
-
-                            (tb, dur, decoder) = if let Some(r) = self.reader.as_mut() {
-                                let track =
-                                match r.tracks().iter().find(|track| track.id == track_id) {
-                                    Some(track) => track,
-                                    _ => {
-                                        let err = "Error reading track";
-                                        self.error = Some(err.to_string());
-                                        let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                                        continue;
-                                    }
-                                };
-
-                                let dec = symphonia::default::get_codecs()
-                                    .make(&track.codec_params, &decode_opts)?;
-
-                                let tb = track.codec_params.time_base;
-
-                                let dur = track
-                                    .codec_params
-                                    .n_frames
-                                    .map(|frames| {
-                                        track.codec_params.start_ts + frames
-                                    });
-                                (tb, dur, Some(dec))
-                            } else {
-                                    let err = "reader is none";
-                                    self.error = Some(err.to_string());
-                                    let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                                    continue;
-                            };
+            // Opening the current entry: handle actions until it is loaded
+            if let Some(loading) = &self.loading {
+                let (actions, loaded) = (self.rx.clone(), loading.rx.clone());
+                select! {
+                    recv(actions) -> a => match a {
+                        Ok(a) => {
+                            if let ActionResult::Break = self.handle_action(&a, &mut audio_output) {
+                                break;
+                            }
                         }
-                        continue;
-                    }
-                    ActionResult::Handled => {}
+                        Err(_) => break,
+                    },
+                    recv(loaded) -> loaded => {
+                        if let Some(loading) = self.loading.take() {
+                            let loaded = loaded.unwrap_or_else(|_| Err(PlayerError::Io("opening failed".to_string())));
+                            self.track_loaded(loading.src, loaded);
+                        }
+                    },
                 }
+                continue;
             }
 
-            // Idle: error state — block until next command
-            if self.error.is_some() {
-                match self.rx.recv() {
-                    Ok(a) => {
-                        match self.handle_action(&a, &mut playing, &mut decoder, &mut audio_output) {
-                            ActionResult::Break => break Ok(0),
-                            _ => {}
-                        }
-                    }
-                    Err(_) => break Ok(0),
+            if self.pending_seek.is_some() && self.error.is_none() && self.reader.is_some() {
+                if let ActionResult::Break = self.seek(&mut audio_output) {
+                    break;
                 }
                 continue;
             }
 
-            // Idle: no reader — block until next command
-            if self.reader.is_none() {
-                match self.rx.recv() {
+            // Buffering: wait for the preroll, handling actions meanwhile
+            let waiting = self.buffering.is_some() && self.playing && self.error.is_none() && self.reader.is_some();
+            if waiting && self.update_buffering(&mut audio_output) {
+                match self.rx.recv_timeout(BUFFERING_POLL) {
                     Ok(a) => {
-                        match self.handle_action(&a, &mut playing, &mut decoder, &mut audio_output) {
-                            ActionResult::Break => break Ok(0),
-                            ActionResult::Continue => {
-                                let track_num: Option<usize> = None;
-
-                                if let Some(reader) = self.reader.as_mut() {
-                                    track = track_num
-                                        .and_then(|t| reader.tracks().get(t))
-                                        .or_else(|| first_supported_track(reader.tracks()));
-
-                                    track_id = track.unwrap().id;
-
-                                    (tb, dur, decoder) = if let Some(r) = self.reader.as_mut() {
-                                        let track =
-                                        match r.tracks().iter().find(|track| track.id == track_id) {
-                                            Some(track) => track,
-                                            _ => {
-                                                let err = "Error reading track";
-                                                self.error = Some(err.to_string());
-                                                let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                                                continue;
-                                            }
-                                        };
-
-                                        let dec = symphonia::default::get_codecs()
-                                            .make(&track.codec_params, &decode_opts)?;
-
-                                        let tb = track.codec_params.time_base;
-
-                                        let dur = track
-                                            .codec_params
-                                            .n_frames
-                                            .map(|frames| {
-                                                track.codec_params.start_ts + frames
-                                            });
-                                        (tb, dur, Some(dec))
-                                    } else {
-                                            let err = "reader is none";
-                                            self.error = Some(err.to_string());
-                                            let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                                            continue;
-                                    };
-                                }
-                            }
-                            ActionResult::Handled => {}
+                        if let ActionResult::Break = self.handle_action(&a, &mut audio_output) {
+                            break;
                         }
                     }
-                    Err(_) => break Ok(0),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
                 }
                 continue;
             }
 
-            // Idle: paused — block until next command
-            if !playing {
+            // Idle: error state, no reader, paused or finished — block until next command
+            if self.error.is_some() || self.reader.is_none() || !self.playing || self.finished {
                 match self.rx.recv() {
                     Ok(a) => {
-                        match self.handle_action(&a, &mut playing, &mut decoder, &mut audio_output) {
-                            ActionResult::Break => break Ok(0),
-                            _ => {}
+                        if let ActionResult::Break = self.handle_action(&a, &mut audio_output) {
+                            break;
                         }
                     }
-                    Err(_) => break Ok(0),
+                    Err(_) => break,
                 }
                 continue;
             }
 
-            let packet = if let Some(reader) = self.reader.as_mut() {
-                match reader.next_packet() {
-                    Ok(packet) => packet,
-                    Err(e) => {
-                        let err = format!("Error reading next packet [{}]", e);
-                        self.error = Some(err.clone());
-                        let _ = self.tx_status.send(PlayerStatus::Error(err));
-                        continue;
-                    },
-                }
-            } else {
-                let err = "Error reading track";
-                self.error = Some(err.to_string());
-                let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                continue;
+            self.play_packet(&mut audio_output);
+        }
+        Ok(0)
+    }
+
+    /// Execute pending seek. Seeks requested while seeking are coalesced so only
+    /// the latest target is executed and reported with `Seeked`.
+    fn seek(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) -> ActionResult {
+        while let Some(seek_target) = self.pending_seek.take() {
+            let Some(reader) = self.reader.as_mut() else {
+                break;
             };
 
-            if packet.track_id() != track_id {
-                continue;
-            }
+            let seek_to = SeekTo::Time {
+                time: Time::from(seek_target),
+                track_id: Some(self.track_id),
+            };
+            match reader.seek(self.seek_mode, seek_to) {
+                Ok(seeked) => {
+                    if let Some(decoder) = self.decoder.as_mut() {
+                        decoder.reset();
+                    }
+                    // Coarse seeks continue from the packet the reader landed on, accurate
+                    // ones from the requested timestamp.
+                    let landed_ts = match self.seek_mode {
+                        SeekMode::Accurate => seeked.required_ts.max(seeked.actual_ts),
+                        SeekMode::Coarse => seeked.actual_ts,
+                    };
+                    self.skip_until = (self.seek_mode == SeekMode::Accurate).then_some(landed_ts);
+                    let position = ts_seconds(landed_ts, self.time_base).unwrap_or(seek_target);
 
-            // If action is a seek, drain any additional seeks queued during audio write
-            // so we execute only the latest target. Non-seek actions are deferred.
-            let mut deferred_action: Option<PlayerActions> = None;
-            let action = if let Some(PlayerActions::Seek(t)) = action {
-                let mut final_t = t;
-                loop {
-                    match self.rx.try_recv() {
-                        Ok(PlayerActions::Seek(next_t)) => final_t = next_t,
-                        Ok(other) => { deferred_action = Some(other); break; }
-                        Err(_) => break,
+                    // Drain any seeks that arrived during reader.seek()
+                    if let ActionResult::Break = self.process_actions(audio_output) {
+                        return ActionResult::Break;
                     }
-                }
-                Some(PlayerActions::Seek(final_t))
-            } else {
-                action
-            };
 
-            if let Some(ref mut decoder) = decoder {
-                match decoder.decode(&packet) {
-                    Ok(decoded) => {
-                        if audio_output.is_none() {
-                            let spec = *decoded.spec();
-                            let duration = decoded.capacity() as u64;
-                            audio_output.replace(try_open(spec, duration).unwrap());
+                    if self.pending_seek.is_none() {
+                        if self.finished {
+                            self.finished = false;
+                            let playing = if self.playing { Playing::Playing } else { Playing::Paused };
+                            let _ = self.tx_status.send(PlayerStatus::SendPlaying(playing));
                         }
+                        self.position = position;
+                        // Channel is empty — send final position only now
+                        let _ = self.tx_status.send(PlayerStatus::Seeked(position));
+                        self.landed_chapter = self
+                            .chapter_seek
+                            .take()
+                            .filter(|&c| chapter_at(&self.chapters, position + CHAPTER_TOLERANCE_SECS) == Some(c));
+                        self.update_chapter();
+                        self.check_preroll();
+                    }
+                }
+                Err(e) => {
+                    // Playback continues from the current position.
+                    self.chapter_seek = None;
+                    let _ = self.tx_status.send(PlayerStatus::Error(
+                        PlayerError::SeekFailed(e.to_string()),
+                    ));
+                }
+            }
+        }
+        ActionResult::Handled
+    }
 
-                        let ts = packet.ts();
-                        let (position, duration) = update_progress(ts, dur, tb);
-                        let _ = self.tx_status.send(PlayerStatus::SendTimeStats(position, duration));
+    /// Read, decode and play the next packet of the current track.
+    fn play_packet(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        // Wait for the preroll instead of blocking in a read, so actions are handled.
+        if self.prefetch.as_ref().is_some_and(|p| p.stalled() && p.fill(self.preroll) < 1.0) {
+            self.start_buffering();
+            return;
+        }
+        let Some(reader) = self.reader.as_mut() else {
+            return;
+        };
+
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(ref e) if is_end_of_stream(e) => {
+                match self.queue.next_index() {
+                    Some(index) => self.play_index(index, true, audio_output),
+                    None => self.finish(audio_output),
+                }
+                return;
+            }
+            Err(e) => {
+                self.set_error(e.into());
+                return;
+            }
+        };
+
+        if update_metadata(reader.as_mut(), &mut self.metadata) {
+            let _ = self.tx_status.send(PlayerStatus::Metadata(self.metadata.clone()));
+        }
+
+        if packet.track_id() != self.track_id {
+            return;
+        }
+
+        let volume = self.output_volume();
+        let Some(decoder) = self.decoder.as_mut() else {
+            return;
+        };
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let mut ts = packet.ts();
+                let trimmed = match self.skip_until {
+                    Some(skip_until) if ts + packet.dur() <= skip_until => return,
+                    Some(skip_until) => {
+                        self.skip_until = None;
+                        let skip = skip_frames(skip_until.saturating_sub(ts), self.time_base, decoded.spec().rate);
+                        ts = ts.max(skip_until);
+                        (skip > 0).then(|| skip_start(&decoded, skip))
+                    }
+                    None => None,
+                };
+                let decoded = match &trimmed {
+                    Some(buf) => buf.as_audio_buffer_ref(),
+                    None => decoded,
+                };
 
-                        if let Some(ref mut audio_output) = audio_output {
-                            audio_output.write(decoded).unwrap()
+                let format = (*decoded.spec(), decoded.capacity() as Duration);
+                // After a gapless transition the output is reused only if the format fits.
+                if let Some((spec, capacity)) = self.output_format {
+                    if spec != format.0 || capacity < format.1 {
+                        if let Some(mut output) = audio_output.take() {
+                            output.flush();
                         }
+                    }
+                }
 
-                        if let Some(PlayerActions::Seek(t)) = action {
-                            let mut seek_target = t;
-
-                            'seek_loop: loop {
-                                // Drain any seeks that arrived during audio write or previous seek
-                                loop {
-                                    match self.rx.try_recv() {
-                                        Ok(PlayerActions::Seek(next_t)) => seek_target = next_t,
-                                        Ok(other) => { deferred_action = Some(other); break; }
-                                        Err(_) => break,
-                                    }
-                                }
-
-                                let ts: Time = seek_target.into();
-                                if let Some(reader) = self.reader.as_mut() {
-                                    if reader.seek(
-                                        SeekMode::Coarse,
-                                        SeekTo::Time {
-                                            time: ts,
-                                            track_id: Some(0),
-                                        },
-                                    ).is_ok() {
-                                        // Drain any seeks that arrived during reader.seek()
-                                        let mut found_newer = false;
-                                        loop {
-                                            match self.rx.try_recv() {
-                                                Ok(PlayerActions::Seek(next_t)) => {
-                                                    seek_target = next_t;
-                                                    found_newer = true;
-                                                }
-                                                Ok(other) => { deferred_action = Some(other); break; }
-                                                Err(_) => break,
-                                            }
-                                        }
-
-                                        if found_newer {
-                                            continue 'seek_loop;
-                                        }
-
-                                        // Channel is empty — send final position only now
-                                        let _ = self.tx_status.send(PlayerStatus::Seeked(seek_target));
-                                    }
-                                }
-                                break 'seek_loop;
-                            }
+                if audio_output.is_none() {
+                    match self.sink.open(format.0, format.1, volume) {
+                        Ok(output) => {
+                            *audio_output = Some(output);
+                            self.output_format = Some(format);
+                        }
+                        Err(e) => {
+                            self.set_error(e.into());
+                            return;
                         }
                     }
-                    Err(Error::DecodeError(err)) => {
-                        let err = &format!("decode error: {}", err);
-                        self.error = Some(err.to_string());
-                        let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                        continue;
+                }
+
+                let (mut position, duration) = update_progress(ts, self.n_frames, self.time_base);
+                if self.live {
+                    position = self.frames_played as f64 / format.0.rate.max(1) as f64;
+                    self.frames_played += decoded.frames() as u64;
+                }
+                self.position = position;
+                let _ = self.tx_status.send(PlayerStatus::SendTimeStats(position, duration));
+
+                if let Some(output) = audio_output {
+                    if let Err(e) = output.write(decoded) {
+                        self.set_error(e.into());
+                        return;
                     }
-                    Err(err) => {
-                        let err = &format!("error: {}", err);
-                        self.error = Some(err.to_string());
-                        let _ = self.tx_status.send(PlayerStatus::Error(err.to_string()));
-                        continue;
+                    if output.take_underrun() {
+                        self.start_buffering();
                     }
-                };
-            }
+                }
 
-            // Handle any non-seek action intercepted during seek drain
-            if let Some(other) = deferred_action {
-                match self.handle_action(&other, &mut playing, &mut decoder, &mut audio_output) {
-                    ActionResult::Break => break 'main Ok(0),
-                    _ => {}
+                self.update_chapter();
+                if self.preload.is_none() && duration > 0.0 && duration - position <= PRELOAD_SECS {
+                    self.start_preload();
                 }
             }
-        };
-        result
+            Err(err) => self.set_error(err.into()),
+        }
     }
 
-    fn open(&mut self, path: &str) -> Result<i32> {
-        let r = match UrlSourceBuf::new(path, Some(self.tx_status.clone())) {
-            Ok(r) => r,
-            Err(e) => {
-                let err = format!("Failed to open URL: {}", e);
-                self.error = Some(err.clone());
-                let _ = self.tx_status.send(PlayerStatus::Error(err));
-                return Ok(1);
+    /// Report the chapter at the current position if it changed.
+    fn update_chapter(&mut self) {
+        let chapter = match self.landed_chapter {
+            Some(c) if self.position < self.chapters[c].start => Some(c),
+            _ => {
+                self.landed_chapter = None;
+                chapter_at(&self.chapters, self.position)
             }
         };
-        let source = Box::new(r);
+        if chapter != self.chapter {
+            self.chapter = chapter;
+            let _ = self.tx_status.send(PlayerStatus::ChapterChanged(chapter));
+        }
+    }
 
-        let hint = Hint::new();
-        let mss = MediaSourceStream::new(source, Default::default());
+    /// Start buffering if the preroll of the current source isn't downloaded yet.
+    fn check_preroll(&mut self) {
+        if self.prefetch.as_ref().is_some_and(|p| p.fill(self.preroll) < 1.0) {
+            self.start_buffering();
+        }
+    }
 
-        let format_opts = FormatOptions
-        {
-            enable_gapless: true,
-            ..Default::default()
-        };
-        let metadata_opts: MetadataOptions = Default::default();
+    fn start_buffering(&mut self) {
+        if self.buffering.is_none() {
+            self.buffering = Some(0.0);
+            let _ = self.tx_status.send(PlayerStatus::BufferingStarted);
+        }
+    }
 
-        match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
-            Ok(probed) => {
-                self.reader = Some(probed.format);
-                self.src = Some(path.to_string());
-                Ok(0)
-            }
-            Err(e) => {
-                println!("input not supported: {:#?}", e);
-                Err(e)
+    fn end_buffering(&mut self) {
+        if self.buffering.take().is_some() {
+            let _ = self.tx_status.send(PlayerStatus::BufferingEnded);
+        }
+    }
+
+    /// Report buffering progress, end buffering once the preroll is downloaded.
+    /// Returns `true` while still buffering.
+    fn update_buffering(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) -> bool {
+        let fill = self.prefetch.as_ref().map_or(1.0, |p| p.fill(self.preroll));
+        if fill >= 1.0 {
+            self.end_buffering();
+            if let Some(output) = audio_output {
+                // The output ran empty while waiting.
+                output.take_underrun();
             }
+            return false;
+        }
+        // Whole percents, to not flood the channel.
+        let fill = (fill * 100.0).floor() / 100.0;
+        if self.buffering != Some(fill) {
+            self.buffering = Some(fill);
+            let _ = self.tx_status.send(PlayerStatus::Buffering(fill));
+        }
+        true
+    }
+
+    /// End of stream: play out buffered audio, then report `Finished`.
+    fn finish(&mut self, audio_output: &mut Option<Box<dyn AudioOutput>>) {
+        if let Some(mut output) = audio_output.take() {
+            output.flush();
         }
+        self.finished = true;
+        let _ = self.tx_status.send(PlayerStatus::Finished);
     }
 
     fn _print_progress(
@@ -473,22 +967,227 @@ impl Drop for PlayerEngine {
     }
 }
 
-fn _ignore_end_of_stream_error(result: Result<()>) -> Result<()> {
-    match result {
-        Err(Error::IoError(err))
-            if err.kind() == std::io::ErrorKind::UnexpectedEof
-                && err.to_string() == "end of stream" =>
-        {
-            // Do not treat "end of stream" as a fatal error. It's the currently only way a
-            // format reader can indicate the media is complete.
-            Ok(())
+#[cfg(feature = "playback")]
+fn default_sink() -> Box<dyn AudioSink> {
+    Box::new(crate::output::CpalSink::default())
+}
+
+/// Without audio devices the player consumes audio in real time, so positions and events
+/// behave as with a device.
+#[cfg(not(feature = "playback"))]
+fn default_sink() -> Box<dyn AudioSink> {
+    Box::new(crate::output::NullSink::realtime())
+}
+
+/// Format readers signal the end of media with an `UnexpectedEof` io error.
+/// It's currently the only way a format reader can indicate the media is complete.
+fn is_end_of_stream(err: &Error) -> bool {
+    matches!(err, Error::IoError(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
+/// Open `path`, probe its format and create a decoder for the first supported track.
+/// Buffer progress is reported through `link`, if any. Playlists open their first entry
+/// that opens.
+pub(crate) fn load_track(
+    path: &str,
+    link: Option<StatusLink>,
+    options: &SourceOptions,
+) -> std::result::Result<LoadedTrack, PlayerError> {
+    open_track(path, link, options, 0, false)
+}
+
+/// `load_track` of an entry of a playlist nested `depth` deep. `hls` opens `path` as HLS
+/// regardless of its extension.
+fn open_track(
+    path: &str,
+    link: Option<StatusLink>,
+    options: &SourceOptions,
+    depth: usize,
+    hls: bool,
+) -> std::result::Result<LoadedTrack, PlayerError> {
+    let mut prefetch = None;
+    let mut metadata = Metadata::default();
+    let mut chapters = Vec::new();
+    let segmented = if hls || is_hls(path) {
+        Some(hls::open(path, link.clone(), options)?)
+    } else if is_dash(path) {
+        Some(dash::open(path, link.clone(), options)?)
+    } else {
+        None
+    };
+    let (mut reader, live, byte_len) = if let Some(reader) = segmented {
+        let live = reader.is_live();
+        (Box::new(reader) as Box<dyn FormatReader>, live, None)
+    } else {
+        let mut source: Box<dyn MediaSource> = if let Some(file_path) = local_path(path) {
+            let f = File::open(file_path).map_err(Url2AudioError::from)?;
+            if is_playlist(path) {
+                return open_playlist(path, &read_text(f)?, link, options, depth);
+            }
+            // Local files are always fully available.
+            if let Some(link) = &link {
+                link.send(PlayerStatus::ChunkAdded(0.0, 1.0));
+            }
+            Box::new(f)
+        } else {
+            let source = UrlSourceBuf::new(path, link.clone(), options)?;
+            if is_playlist(path) || source.content_type().is_some_and(is_playlist_type) {
+                return open_playlist(path, &read_text(source)?, link, options, depth);
+            }
+            prefetch = Some(source.prefetch());
+            Box::new(source)
+        };
+        let live = !source.is_seekable();
+        let byte_len = source.byte_len();
+        // Symphonia skips chapters, they are read before probing.
+        if !live {
+            chapters = read_chapters(&mut source);
+            source.seek(SeekFrom::Start(0)).map_err(Url2AudioError::from)?;
+        }
+
+        let mut probed = probe(source, extension(path))?;
+        // Tags before the container (e.g. ID3v2 in front of MP3 frames), then the container's.
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            metadata.merge(revision);
+        }
+        (probed.format, live, byte_len)
+    };
+    if let Some(revision) = reader.metadata().current() {
+        metadata.merge(revision);
+    }
+    update_metadata(reader.as_mut(), &mut metadata);
+
+    let track = first_supported_track(reader.tracks()).ok_or(PlayerError::NoSupportedTrack)?;
+    let decode_opts: DecoderOptions = Default::default();
+    let decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &decode_opts)
+        .map_err(|e| PlayerError::UnsupportedFormat(e.to_string()))?;
+
+    let n_frames = track.codec_params.n_frames;
+    let duration = n_frames.and_then(|frames| ts_seconds(frames, track.codec_params.time_base));
+    if let (Some(prefetch), Some(len), Some(duration)) = (&prefetch, byte_len, duration) {
+        prefetch.set_byte_rate(len as f64 / duration);
+    }
+    if let Some(last) = chapters.last_mut() {
+        last.end = last.end.or(duration);
+    }
+
+    Ok(LoadedTrack {
+        src: path.to_string(),
+        track_id: track.id,
+        time_base: track.codec_params.time_base,
+        n_frames: n_frames.map(|frames| track.codec_params.start_ts + frames),
+        live,
+        metadata,
+        chapters,
+        prefetch,
+        reader,
+        decoder,
+        link,
+    })
+}
+
+/// Open the first entry of playlist `text` (fetched from `path`) that opens.
+fn open_playlist(
+    path: &str,
+    text: &str,
+    link: Option<StatusLink>,
+    options: &SourceOptions,
+    depth: usize,
+) -> std::result::Result<LoadedTrack, PlayerError> {
+    // HLS served as `.m3u` or without extension.
+    if is_hls_playlist(text) {
+        return open_track(path, link, options, depth, true);
+    }
+    if depth >= MAX_PLAYLIST_DEPTH {
+        return Err(PlayerError::UnsupportedFormat("nested playlists".to_string()));
+    }
+    let base = manifest_url(path)?;
+    let mut last_error = None;
+    for entry in parse_playlist(text, base.as_str())? {
+        // Statuses of entries failing to open are dropped with their link.
+        let entry_link = link.as_ref().map(|_| StatusLink::default());
+        match open_track(&entry.url, entry_link, options, depth + 1, false) {
+            Ok(mut track) => {
+                if track.metadata.title.is_none() {
+                    track.metadata.title = entry.title;
+                }
+                return Ok(track);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or(PlayerError::NoSupportedTrack))
+}
+
+/// Text of a playlist file, at most `MAX_PLAYLIST_LEN` bytes of it.
+fn read_text(source: impl Read) -> std::result::Result<String, PlayerError> {
+    let mut data = Vec::new();
+    source
+        .take(MAX_PLAYLIST_LEN)
+        .read_to_end(&mut data)
+        .map_err(Url2AudioError::from)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Probe the format of `source`, with the file extension `ext` as hint.
+pub(crate) fn probe(source: Box<dyn MediaSource>, ext: Option<&str>) -> std::result::Result<ProbeResult, PlayerError> {
+    let mut hint = Hint::new();
+    if let Some(ext) = ext {
+        hint.with_extension(ext);
+    }
+    let mss = MediaSourceStream::new(source, Default::default());
+
+    let format_opts = FormatOptions
+    {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let metadata_opts: MetadataOptions = Default::default();
+
+    symphonia::default::get_probe()
+        .format(&hint, mss, &format_opts, &metadata_opts)
+        .map_err(|e| match e {
+            Error::IoError(e) => PlayerError::from(e),
+            e => PlayerError::UnsupportedFormat(e.to_string()),
+        })
+}
+
+/// Merge metadata revisions the reader queued since the last call. Returns `true` if there
+/// were any.
+pub(crate) fn update_metadata(reader: &mut dyn FormatReader, metadata: &mut Metadata) -> bool {
+    let mut log = reader.metadata();
+    let mut updated = false;
+    while !log.is_latest() {
+        log.pop();
+        if let Some(revision) = log.current() {
+            metadata.merge(revision);
+            updated = true;
         }
-        _ => result,
+    }
+    updated
+}
+
+/// `ts` in seconds.
+pub(crate) fn ts_seconds(ts: u64, tb: Option<TimeBase>) -> Option<f64> {
+    let t = tb?.calc_time(ts);
+    Some(t.seconds as f64 + t.frac)
+}
+
+/// Number of frames at `rate` covering `dur` timestamp units.
+pub(crate) fn skip_frames(dur: u64, tb: Option<TimeBase>, rate: u32) -> usize {
+    match ts_seconds(dur, tb) {
+        Some(secs) => (secs * rate as f64).round() as usize,
+        None => dur as usize,
     }
 }
 
-pub fn try_open(spec: SignalSpec, duration: Duration) -> cpalaudio::Result<Box<dyn AudioOutput>> {
-    CpalAudioOutput::try_open(spec, duration)
+/// Copy of `decoded` without its first `frames` frames.
+pub(crate) fn skip_start(decoded: &AudioBufferRef<'_>, frames: usize) -> AudioBuffer<f32> {
+    let mut buf = decoded.make_equivalent::<f32>();
+    decoded.convert(&mut buf);
+    buf.trim(frames.min(buf.frames()), 0);
+    buf
 }
 
 fn update_progress(
@@ -514,3 +1213,93 @@ fn first_supported_track(tracks: &[Track]) -> Option<&Track> {
         .iter()
         .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
 }
+
+/// Resolve `src` to a local file path if it is a `file://` URL or a plain path.
+/// Returns `None` for remote URLs (`http://`, `https://`, ...).
+pub(crate) fn local_path(src: &str) -> Option<PathBuf> {
+    match url::Url::parse(src) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        // Single letter "schemes" are Windows drive letters (`C:\music\a.mp3`).
+        Ok(url) if url.scheme().len() > 1 => None,
+        _ => Some(PathBuf::from(src)),
+    }
+}
+
+/// File extension of `src` (path or URL, query and fragment ignored), used as probe hint.
+pub(crate) fn extension(src: &str) -> Option<&str> {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    let name = path.rsplit(['/', '\\']).next()?;
+    Path::new(name).extension().and_then(|e| e.to_str())
+}
+
+#[test]
+fn local_path_detection() {
+    assert_eq!(local_path("/home/user/episode.mp3"), Some(PathBuf::from("/home/user/episode.mp3")));
+    assert_eq!(local_path("music/a.flac"), Some(PathBuf::from("music/a.flac")));
+    assert_eq!(local_path("file:///tmp/my%20episode.mp3"), Some(PathBuf::from("/tmp/my episode.mp3")));
+    assert_eq!(local_path("https://podcast.daskoimladja.com/media/a.mp3"), None);
+    assert_eq!(local_path("http://stream.daskoimladja.com:9000/stream"), None);
+}
+
+#[test]
+fn extension_hint() {
+    assert_eq!(extension("/tmp/a.mp3"), Some("mp3"));
+    assert_eq!(extension("https://host/media/b.ogg?token=1.2"), Some("ogg"));
+    assert_eq!(extension("http://stream.daskoimladja.com:9000/stream"), None);
+}
+
+#[test]
+fn chapter_changes_at_its_start() {
+    let (_tx, rx) = crossbeam_channel::unbounded();
+    let (tx_status, rx_status) = crossbeam_channel::unbounded();
+    let mut engine = PlayerEngine::new(rx, tx_status);
+    let chapter = |start, end| Chapter {
+        title: None,
+        start,
+        end: Some(end),
+        url: None,
+        image: None,
+    };
+    engine.chapters = vec![chapter(0.0, 1.0), chapter(1.0, 2.0)];
+    let changes = |engine: &mut PlayerEngine, position| {
+        engine.position = position;
+        engine.update_chapter();
+        rx_status.try_iter().filter(|s| matches!(s, PlayerStatus::ChapterChanged(_))).collect::<Vec<_>>()
+    };
+
+    assert_eq!(changes(&mut engine, 0.0), [PlayerStatus::ChapterChanged(Some(0))]);
+    assert_eq!(changes(&mut engine, 1.0 - CHAPTER_TOLERANCE_SECS / 2.0), []);
+    assert_eq!(changes(&mut engine, 0.999), []);
+    assert_eq!(changes(&mut engine, 1.0), [PlayerStatus::ChapterChanged(Some(1))]);
+
+    // A chapter seek landing just before the start is in the chapter.
+    assert_eq!(changes(&mut engine, 0.5), [PlayerStatus::ChapterChanged(Some(0))]);
+    engine.landed_chapter = Some(1);
+    assert_eq!(changes(&mut engine, 0.9), [PlayerStatus::ChapterChanged(Some(1))]);
+    assert_eq!(changes(&mut engine, 1.1), []);
+    assert_eq!(engine.landed_chapter, None);
+}
+
+#[test]
+fn live_streams_reject_chapter_seeks() {
+    let (_tx, rx) = crossbeam_channel::unbounded();
+    let (tx_status, rx_status) = crossbeam_channel::unbounded();
+    let mut engine = PlayerEngine::new(rx, tx_status);
+    let chapter = |start| Chapter {
+        title: None,
+        start,
+        end: None,
+        url: None,
+        image: None,
+    };
+    engine.chapters = vec![chapter(0.0), chapter(60.0)];
+    engine.chapter = Some(0);
+    engine.live = true;
+
+    let actions = [PlayerActions::NextChapter, PlayerActions::PreviousChapter, PlayerActions::SeekToChapter(1)];
+    for action in &actions {
+        engine.handle_action(action, &mut None);
+        assert_eq!(engine.pending_seek, None);
+        assert_eq!(rx_status.try_recv(), Ok(PlayerStatus::Error(PlayerError::NotSeekable)));
+    }
+}
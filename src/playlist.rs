@@ -0,0 +1,279 @@
+//! Playlist files: M3U (plain and extended), PLS and XSPF.
+//!
+//! Radio directories link to playlists rather than to the stream itself. Opening a playlist
+//! plays its first entry that opens (see `Player::open`); `parse_playlist` gives all entries,
+//! e.g. to put them into the queue.
+
+use roxmltree::Document;
+use url::Url;
+
+use crate::player_engine::extension;
+use crate::PlayerError;
+
+/// Content types of playlist responses. The HLS ones are checked for HLS tags.
+const PLAYLIST_TYPES: [&str; 8] = [
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+    "application/x-mpegurl",
+    "application/vnd.apple.mpegurl",
+    "audio/x-scpls",
+    "audio/scpls",
+    "application/pls+xml",
+    "application/xspf+xml",
+];
+
+/// Entry of a playlist file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistEntry {
+    /// Absolute url or path of the entry.
+    pub url: String,
+    pub title: Option<String>,
+    /// Duration in seconds, if the playlist gives one.
+    pub duration: Option<f64>,
+}
+
+/// Whether `src` is a playlist file by its extension. `.m3u8` is left to HLS.
+pub(crate) fn is_playlist(src: &str) -> bool {
+    extension(src).is_some_and(|ext| ["m3u", "pls", "xspf"].iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Whether `content_type` (value of the `Content-Type` header) is a playlist.
+pub(crate) fn is_playlist_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    PLAYLIST_TYPES.iter().any(|t| mime.eq_ignore_ascii_case(t))
+}
+
+/// Whether the M3U `text` is an HLS playlist rather than a list of streams.
+pub(crate) fn is_hls_playlist(text: &str) -> bool {
+    text.lines().any(|line| line.trim_start().starts_with("#EXT-X-"))
+}
+
+/// Parse playlist `text`, with relative entries resolved against `base` (url of the
+/// playlist). The format is detected from the content.
+pub fn parse_playlist(text: &str, base: &str) -> Result<Vec<PlaylistEntry>, PlayerError> {
+    let base = Url::parse(base).ok();
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let entries = if text.get(..10).is_some_and(|s| s.eq_ignore_ascii_case("[playlist]")) {
+        parse_pls(text, base.as_ref())
+    } else if text.starts_with('<') {
+        parse_xspf(text, base.as_ref())?
+    } else {
+        parse_m3u(text, base.as_ref())
+    };
+    if entries.is_empty() {
+        return Err(PlayerError::UnsupportedFormat("playlist without entries".to_string()));
+    }
+    Ok(entries)
+}
+
+/// `location` resolved against `base`. Absolute urls, paths and entries of playlists
+/// without a url base are kept.
+fn resolve(location: &str, base: Option<&Url>) -> String {
+    let location = location.trim();
+    match (Url::parse(location), base) {
+        // Single letter "schemes" are Windows drive letters.
+        (Ok(url), _) if url.scheme().len() > 1 => location.to_string(),
+        (_, Some(base)) if !location.starts_with('/') || base.scheme() != "file" => {
+            base.join(location).map_or_else(|_| location.to_string(), String::from)
+        }
+        _ => location.to_string(),
+    }
+}
+
+/// Non-negative seconds; playlists use `-1` for unknown (live) durations.
+fn duration(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|d| *d >= 0.0)
+}
+
+fn text_value(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn parse_m3u(text: &str, base: Option<&Url>) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut info = None;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // `#EXTINF:<seconds> [attributes],<title>`
+            let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let seconds = head.split_whitespace().next().unwrap_or_default();
+            info = Some((duration(seconds), text_value(title)));
+        } else if !line.starts_with('#') {
+            let (duration, title) = info.take().unwrap_or_default();
+            entries.push(PlaylistEntry {
+                url: resolve(line, base),
+                title,
+                duration,
+            });
+        }
+    }
+    entries
+}
+
+fn parse_pls(text: &str, base: Option<&Url>) -> Vec<PlaylistEntry> {
+    // Entries are numbered from 1, keys may come in any order.
+    let mut numbered: Vec<(usize, PlaylistEntry)> = Vec::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let (field, number) = key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()));
+        let Ok(number) = number.parse::<usize>() else {
+            continue;
+        };
+        let index = match numbered.iter().position(|(n, _)| *n == number) {
+            Some(index) => index,
+            None => {
+                let entry = PlaylistEntry {
+                    url: String::new(),
+                    title: None,
+                    duration: None,
+                };
+                numbered.push((number, entry));
+                numbered.len() - 1
+            }
+        };
+        let entry = &mut numbered[index].1;
+        match field {
+            "file" => entry.url = resolve(value, base),
+            "title" => entry.title = text_value(value),
+            "length" => entry.duration = duration(value),
+            _ => {}
+        }
+    }
+    numbered.sort_by_key(|(n, _)| *n);
+    numbered.into_iter().map(|(_, e)| e).filter(|e| !e.url.is_empty()).collect()
+}
+
+fn parse_xspf(text: &str, base: Option<&Url>) -> Result<Vec<PlaylistEntry>, PlayerError> {
+    let invalid = |message: String| PlayerError::UnsupportedFormat(format!("XSPF: {}", message));
+    let doc = Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let playlist = doc.root_element();
+    if !playlist.has_tag_name("playlist") {
+        return Err(invalid("not a playlist".to_string()));
+    }
+    let child = |node: roxmltree::Node, name: &str| {
+        node.children().find(|n| n.has_tag_name(name)).and_then(|n| n.text()).and_then(text_value)
+    };
+    // `xml:base` of the playlist takes precedence over its url.
+    let xml_base = playlist
+        .attribute(("http://www.w3.org/XML/1998/namespace", "base"))
+        .and_then(|b| match base {
+            Some(base) => base.join(b).ok(),
+            None => Url::parse(b).ok(),
+        });
+    let base = xml_base.as_ref().or(base);
+
+    let tracks = playlist.children().filter(|n| n.has_tag_name("trackList")).flat_map(|list| list.children());
+    Ok(tracks
+        .filter(|n| n.has_tag_name("track"))
+        .filter_map(|track| {
+            Some(PlaylistEntry {
+                url: resolve(&child(track, "location")?, base),
+                title: child(track, "title"),
+                duration: child(track, "duration").and_then(|ms| duration(&ms)).map(|ms| ms / 1000.0),
+            })
+        })
+        .collect())
+}
+
+#[test]
+fn parses_playlist_formats() {
+    let base = "https://radio.example.com/stations/jazz.m3u";
+    let m3u = "#EXTM3U
+#EXTINF:-1 tvg-logo=\"logo.png\",Jazz FM
+http://stream.example.com:8000/jazz
+#EXTINF:215,Artist - Track
+../music/track.mp3
+
+backup.aac
+";
+    let entries = parse_playlist(m3u, base).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].url, "http://stream.example.com:8000/jazz");
+    assert_eq!(entries[0].title.as_deref(), Some("Jazz FM"));
+    assert_eq!(entries[0].duration, None);
+    assert_eq!(entries[1].url, "https://radio.example.com/music/track.mp3");
+    assert_eq!(entries[1].duration, Some(215.0));
+    assert_eq!(entries[2].url, "https://radio.example.com/stations/backup.aac");
+    assert_eq!(entries[2].title, None);
+
+    let pls = "[playlist]
+NumberOfEntries=2
+File2=http://backup.example.com/jazz
+Title2=Jazz FM (backup)
+File1=http://main.example.com/jazz
+Title1=Jazz FM
+Length1=-1
+Version=2
+";
+    let entries = parse_playlist(pls, base).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].url, "http://main.example.com/jazz");
+    assert_eq!(entries[0].title.as_deref(), Some("Jazz FM"));
+    assert_eq!(entries[1].title.as_deref(), Some("Jazz FM (backup)"));
+
+    let xspf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <trackList>
+    <track>
+      <location>songs/first.ogg</location>
+      <title>First</title>
+      <duration>183500</duration>
+    </track>
+    <track><title>No location</title></track>
+  </trackList>
+</playlist>"#;
+    let entries = parse_playlist(xspf, base).unwrap();
+    assert_eq!(
+        entries,
+        [PlaylistEntry {
+            url: "https://radio.example.com/stations/songs/first.ogg".to_string(),
+            title: Some("First".to_string()),
+            duration: Some(183.5),
+        }]
+    );
+
+    // Local playlists keep absolute paths.
+    let entries = parse_playlist("/music/a.flac\nb.flac\n", "file:///home/user/list.m3u").unwrap();
+    assert_eq!(entries[0].url, "/music/a.flac");
+    assert_eq!(entries[1].url, "file:///home/user/b.flac");
+
+    assert!(parse_playlist("#EXTM3U\n", base).is_err());
+    assert!(is_playlist("https://radio.example.com/listen.pls?id=1"));
+    assert!(!is_playlist("https://radio.example.com/live.m3u8"));
+    assert!(is_playlist_type("audio/x-scpls; charset=utf-8"));
+    assert!(is_hls_playlist("#EXTM3U\n#EXT-X-TARGETDURATION:6\n"));
+}
+
+#[test]
+fn opens_first_working_entry() {
+    use crate::test_util::{http_response, http_server, temp_path, wav_bytes};
+    use crate::Decoder;
+
+    let wav = wav_bytes(8000, 1, 8000, |i| (i % 1000) as i16);
+    let url = http_server(move |req| match req.path.as_str() {
+        "/listen" => {
+            let pls = b"[playlist]\nFile1=/offline\nFile2=/stream.wav\nTitle2=Test FM\nNumberOfEntries=2\n";
+            http_response(200, &[("content-type", "audio/x-scpls".to_string())], pls)
+        }
+        "/stream.wav" => http_response(200, &[("content-length", wav.len().to_string())], &wav),
+        _ => http_response(404, &[], b""),
+    });
+
+    // Detected by the content type, the first entry fails.
+    let mut decoder = Decoder::open(&format!("{}/listen", url)).unwrap();
+    assert_eq!(decoder.metadata().title.as_deref(), Some("Test FM"));
+    assert_eq!(decoder.by_ref().map(|b| b.unwrap().samples.len()).sum::<usize>(), 8000);
+
+    // Local playlist with a relative entry.
+    let dir = temp_path("playlist");
+    std::fs::create_dir_all(&dir).unwrap();
+    let list = dir.join("list.m3u");
+    std::fs::write(&list, format!("missing.mp3\n{}/stream.wav\n", url)).unwrap();
+    assert!(Decoder::open(list.to_str().unwrap()).is_ok());
+    std::fs::write(&list, "missing.mp3\n").unwrap();
+    assert!(Decoder::open(list.to_str().unwrap()).is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,139 @@
+/// List of sources to play with the position of the currently playing one.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct PlayQueue {
+    items: Vec<String>,
+    current: Option<usize>,
+}
+
+/// Result of `PlayQueue::remove`.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Removed {
+    /// Index was out of range, nothing removed.
+    None,
+    /// Removed entry other than the current one.
+    Other,
+    /// Removed the current entry. Queue has no current entry anymore.
+    Current,
+}
+
+impl PlayQueue {
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.items.get(index).map(|s| s.as_str())
+    }
+
+    /// Index of the entry after the current one.
+    pub fn next_index(&self) -> Option<usize> {
+        self.current
+            .map(|c| c + 1)
+            .filter(|&i| i < self.items.len())
+    }
+
+    /// Index of the entry before the current one.
+    pub fn previous_index(&self) -> Option<usize> {
+        self.current.and_then(|c| c.checked_sub(1))
+    }
+
+    /// Make `index` the current entry. Returns `false` if index is out of range.
+    pub fn set_current(&mut self, index: usize) -> bool {
+        if index < self.items.len() {
+            self.current = Some(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Replace the queue with a single entry and make it current.
+    pub fn replace(&mut self, src: &str) {
+        self.items = vec![src.to_string()];
+        self.current = Some(0);
+    }
+
+    /// Append `src` to the end. Returns index of the new entry.
+    pub fn push(&mut self, src: &str) -> usize {
+        self.items.push(src.to_string());
+        self.items.len() - 1
+    }
+
+    /// Insert `src` at `index` (clamped to the queue length). Returns index of the new entry.
+    pub fn insert(&mut self, index: usize, src: &str) -> usize {
+        let index = index.min(self.items.len());
+        self.items.insert(index, src.to_string());
+        if let Some(c) = self.current.as_mut() {
+            if *c >= index {
+                *c += 1;
+            }
+        }
+        index
+    }
+
+    pub fn remove(&mut self, index: usize) -> Removed {
+        if index >= self.items.len() {
+            return Removed::None;
+        }
+        self.items.remove(index);
+        match self.current {
+            Some(c) if c == index => {
+                self.current = None;
+                Removed::Current
+            }
+            Some(c) if c > index => {
+                self.current = Some(c - 1);
+                Removed::Other
+            }
+            _ => Removed::Other,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.current = None;
+    }
+}
+
+#[test]
+fn queue_navigation() {
+    let mut q = PlayQueue::default();
+    assert_eq!(q.next_index(), None);
+    q.replace("a");
+    q.push("b");
+    q.push("c");
+    assert_eq!(q.current(), Some(0));
+    assert_eq!(q.next_index(), Some(1));
+    assert_eq!(q.previous_index(), None);
+    assert!(q.set_current(2));
+    assert_eq!(q.next_index(), None);
+    assert_eq!(q.previous_index(), Some(1));
+    assert!(!q.set_current(3));
+    assert_eq!(q.current(), Some(2));
+}
+
+#[test]
+fn queue_insert_remove_keeps_current() {
+    let mut q = PlayQueue::default();
+    q.replace("a");
+    q.push("b");
+    q.set_current(1);
+
+    assert_eq!(q.insert(0, "z"), 0);
+    assert_eq!(q.current(), Some(2));
+    assert_eq!(q.get(2), Some("b"));
+    assert_eq!(q.insert(10, "end"), 3);
+
+    assert_eq!(q.remove(0), Removed::Other);
+    assert_eq!(q.current(), Some(1));
+    assert_eq!(q.remove(2), Removed::Other);
+    assert_eq!(q.current(), Some(1));
+    assert_eq!(q.remove(5), Removed::None);
+    assert_eq!(q.remove(1), Removed::Current);
+    assert_eq!(q.current(), None);
+    assert_eq!(q.items(), ["a".to_string()]);
+}
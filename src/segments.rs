@@ -0,0 +1,458 @@
+//! Segmented streams (HLS, DASH): a list of media segments fetched one after another by a
+//! background thread and read as one stream.
+//!
+//! MPEG-TS segments are demuxed to their audio elementary stream, packed audio (AAC, MP3) and
+//! fragmented MP4 segments are passed through after their init section. Live lists are
+//! reloaded from the manifest while playing. Seeks in VOD lists restart the stream at the
+//! segment containing the target time.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender};
+use symphonia::core::errors::{seek_error, unsupported_error, Error, Result, SeekErrorKind};
+use symphonia::core::formats::{Cue, FormatOptions, FormatReader, Packet, SeekMode, SeekTo, SeekedTo, Track};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::Metadata;
+use symphonia::core::units::Time;
+use url::Url;
+
+use crate::http::{is_retryable, HttpClient, ReconnectPolicy, SourceOptions};
+use crate::mpegts::{is_transport_stream, TsDemuxer};
+use crate::player_engine::{extension, local_path, probe, ts_seconds, PlayerStatus, StatusLink};
+use crate::{PlayerError, Url2AudioError};
+
+/// Segments fetched ahead of the one being read.
+const SEGMENTS_AHEAD: usize = 2;
+/// Live playback starts this many segments before the end of the list.
+const LIVE_START_SEGMENTS: usize = 3;
+
+/// Segments of one audio stream, as listed by the manifest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SegmentList {
+    /// Max segment duration, live lists are reloaded after half of it.
+    pub target_duration: f64,
+    pub segments: Vec<Segment>,
+    /// No segments are added, the list is VOD.
+    pub ended: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Segment {
+    /// Increases from segment to segment, identifies segments across reloads.
+    pub sequence: u64,
+    pub uri: String,
+    pub duration: f64,
+    pub range: Option<ByteRange>,
+    /// Init section, e.g. of fragmented MP4 segments.
+    pub map: Option<InitSection>,
+    pub encrypted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ByteRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct InitSection {
+    pub uri: String,
+    pub range: Option<ByteRange>,
+}
+
+impl SegmentList {
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+
+    /// Start time of segment `index`.
+    pub fn start_of(&self, index: usize) -> f64 {
+        self.segments[..index].iter().map(|s| s.duration).sum()
+    }
+
+    /// Index of the segment containing `time`, the last one for times past the end.
+    pub fn segment_at(&self, time: f64) -> usize {
+        let mut end = 0.0;
+        for (index, segment) in self.segments.iter().enumerate() {
+            end += segment.duration;
+            if time < end {
+                return index;
+            }
+        }
+        self.segments.len().saturating_sub(1)
+    }
+}
+
+/// Manifest a live segment list is reloaded from.
+pub(crate) trait Manifest: Send + Sync {
+    fn reload(&self, fetcher: &Fetcher) -> std::result::Result<SegmentList, PlayerError>;
+}
+
+/// Fetches manifests and segments, retrying failed requests according to `reconnect`.
+#[derive(Clone)]
+pub(crate) struct Fetcher {
+    client: HttpClient,
+    reconnect: ReconnectPolicy,
+    link: Option<StatusLink>,
+}
+
+impl Fetcher {
+    pub fn new(options: &SourceOptions, link: Option<StatusLink>) -> std::result::Result<Self, PlayerError> {
+        Ok(Fetcher {
+            client: HttpClient::new(&options.http, options.transport.clone())?,
+            reconnect: options.reconnect.clone(),
+            link,
+        })
+    }
+
+    /// Body of `url` (`http(s)://` or `file://`), or of `range` of it.
+    pub fn fetch(&self, url: &str, range: Option<&ByteRange>) -> io::Result<Vec<u8>> {
+        if let Some(path) = Url::parse(url).ok().filter(|u| u.scheme() == "file").and_then(|u| u.to_file_path().ok()) {
+            let data = std::fs::read(path)?;
+            return Ok(match range {
+                Some(r) => data.into_iter().skip(r.start as usize).take(r.len as usize).collect(),
+                None => data,
+            });
+        }
+
+        let range = range.map(|r| format!("bytes={}-{}", r.start, (r.start + r.len).saturating_sub(1)));
+        let headers: Vec<(&str, &str)> = range.iter().map(|r| ("Range", r.as_str())).collect();
+        let mut attempt = 0;
+        loop {
+            let result = self.client.get(url, None, &headers).map_err(io_error).and_then(|mut response| {
+                let mut body = Vec::new();
+                response.body.read_to_end(&mut body)?;
+                Ok(body)
+            });
+            let err = match result {
+                Ok(body) => {
+                    if attempt > 0 {
+                        self.send(PlayerStatus::Reconnected);
+                    }
+                    return Ok(body);
+                }
+                Err(e) => e,
+            };
+            // E.g. 404 of an expired segment.
+            let status = err.get_ref().and_then(|e| e.downcast_ref::<Url2AudioError>());
+            if matches!(status, Some(Url2AudioError::Status(code)) if !is_retryable(*code)) || attempt >= self.reconnect.max_retries {
+                return Err(err);
+            }
+            attempt += 1;
+            self.send(PlayerStatus::Reconnecting(attempt));
+            std::thread::sleep(self.reconnect.delay(attempt));
+        }
+    }
+
+    /// Manifest text at `url`.
+    pub fn fetch_text(&self, url: &str) -> std::result::Result<String, PlayerError> {
+        Ok(String::from_utf8_lossy(&self.fetch(url, None)?).into_owned())
+    }
+
+    fn send(&self, status: PlayerStatus) {
+        if let Some(link) = &self.link {
+            link.send(status);
+        }
+    }
+}
+
+fn io_error(e: Url2AudioError) -> io::Error {
+    match e {
+        Url2AudioError::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// Url of the manifest at `src`, a url or a local path.
+pub(crate) fn manifest_url(src: &str) -> std::result::Result<Url, PlayerError> {
+    match local_path(src) {
+        Some(path) => std::path::absolute(&path)
+            .ok()
+            .and_then(|path| Url::from_file_path(path).ok())
+            .ok_or_else(|| PlayerError::Io(format!("invalid path {}", src))),
+        None => Url::parse(src).map_err(|e| PlayerError::Io(e.to_string())),
+    }
+}
+
+/// Segment list being played, from which streams starting at any segment are opened.
+#[derive(Clone)]
+struct SegmentStream {
+    fetcher: Fetcher,
+    manifest: Arc<dyn Manifest>,
+    list: SegmentList,
+}
+
+impl SegmentStream {
+    /// Probe the stream starting at segment `index`.
+    fn open_reader(&self, index: usize) -> std::result::Result<Box<dyn FormatReader>, PlayerError> {
+        // Transport streams are demuxed, their extension says nothing about the audio.
+        let ext = self
+            .list
+            .segments
+            .get(index)
+            .and_then(|s| extension(&s.uri))
+            .filter(|ext| !ext.eq_ignore_ascii_case("ts"));
+        let source = SegmentSource::spawn(self.clone(), index);
+        Ok(probe(Box::new(source), ext)?.format)
+    }
+}
+
+/// Segments read as one stream. Not seekable, seeks open a new source.
+struct SegmentSource {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    data: io::Cursor<Vec<u8>>,
+    pos: u64,
+}
+
+impl SegmentSource {
+    fn spawn(stream: SegmentStream, index: usize) -> Self {
+        let (tx, rx) = crossbeam_channel::bounded(SEGMENTS_AHEAD);
+        let next = stream.list.segments.get(index).map_or(0, |s| s.sequence);
+        let worker = Worker {
+            stream,
+            next,
+            demuxer: None,
+            init: None,
+        };
+        std::thread::spawn(move || worker.run(tx));
+        SegmentSource {
+            rx,
+            data: Default::default(),
+            pos: 0,
+        }
+    }
+}
+
+impl MediaSource for SegmentSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Read for SegmentSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.data.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            match self.rx.recv() {
+                Ok(segment) => self.data = io::Cursor::new(segment?),
+                // Worker finished: end of a VOD list.
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+impl Seek for SegmentSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.pos),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "segmented source is not seekable")),
+        }
+    }
+}
+
+/// Fetches segments of a `SegmentSource` in background.
+struct Worker {
+    stream: SegmentStream,
+    /// Sequence number of the next segment.
+    next: u64,
+    demuxer: Option<TsDemuxer>,
+    /// Init section written last.
+    init: Option<InitSection>,
+}
+
+impl Worker {
+    /// Send segment data until the list ends, the source is dropped or fetching fails.
+    fn run(mut self, tx: Sender<io::Result<Vec<u8>>>) {
+        loop {
+            let result = match self.next_segment() {
+                Ok(Some(data)) => Ok(data),
+                Ok(None) => return,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if tx.send(result).is_err() || failed {
+                return;
+            }
+        }
+    }
+
+    /// Data of the next segment, preceded by its init section if it changed. `None` at the
+    /// end of a VOD list.
+    fn next_segment(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let index = loop {
+            // Segments that left the live window are skipped.
+            match self.stream.list.segments.iter().position(|s| s.sequence >= self.next) {
+                Some(index) => break index,
+                None if self.stream.list.ended => return Ok(None),
+                None => self.reload()?,
+            }
+        };
+        let segment = self.stream.list.segments[index].clone();
+        if segment.encrypted {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "encrypted segments are not supported"));
+        }
+
+        let mut data = Vec::new();
+        if segment.map != self.init {
+            if let Some(map) = &segment.map {
+                data = self.stream.fetcher.fetch(&map.uri, map.range.as_ref())?;
+            }
+            self.init = segment.map.clone();
+        }
+        let body = self.stream.fetcher.fetch(&segment.uri, segment.range.as_ref())?;
+        if self.demuxer.is_some() || is_transport_stream(&body) {
+            self.demuxer.get_or_insert_default().demux(&body, &mut data)?;
+        } else {
+            data.extend_from_slice(&body);
+        }
+
+        let list = &self.stream.list;
+        let total = list.duration();
+        if list.ended && total > 0.0 {
+            let start = list.start_of(index);
+            let end = start + segment.duration;
+            self.stream.fetcher.send(PlayerStatus::ChunkAdded((start / total) as f32, (end / total) as f32));
+        }
+        self.next = segment.sequence + 1;
+        Ok(Some(data))
+    }
+
+    /// Wait for new segments of a live list and reload it.
+    fn reload(&mut self) -> io::Result<()> {
+        let wait = (self.stream.list.target_duration / 2.0).max(0.5);
+        std::thread::sleep(Duration::from_secs_f64(wait));
+        self.stream.list = self.stream.manifest.reload(&self.stream.fetcher).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+/// Format reader of a segmented stream: reads packets of the format probed in the segments,
+/// with timestamps counted from the start of the list.
+pub(crate) struct SegmentReader {
+    stream: SegmentStream,
+    inner: Box<dyn FormatReader>,
+    /// Tracks of `inner` with the duration of the list.
+    tracks: Vec<Track>,
+    /// Timestamp of the segment `inner` started at.
+    start_ts: u64,
+    /// Added to the timestamps of `inner`, set from its first packet: readers count from the
+    /// segment they started at (WAV, MPEG-TS) or from the start of the stream (fMP4 `tfdt`).
+    offset: Option<i64>,
+}
+
+impl SegmentReader {
+    /// Probe the segments of `list`. VOD lists start at the first segment, live ones near
+    /// the end.
+    pub fn open(
+        fetcher: Fetcher,
+        manifest: Arc<dyn Manifest>,
+        list: SegmentList,
+    ) -> std::result::Result<Self, PlayerError> {
+        if list.segments.is_empty() {
+            return Err(PlayerError::UnsupportedFormat("manifest without segments".to_string()));
+        }
+        if list.segments.iter().any(|s| s.encrypted) {
+            return Err(PlayerError::UnsupportedFormat("encrypted segments".to_string()));
+        }
+
+        let start = if list.ended { 0 } else { list.segments.len().saturating_sub(LIVE_START_SEGMENTS) };
+        let stream = SegmentStream {
+            fetcher,
+            manifest,
+            list,
+        };
+        let inner = stream.open_reader(start)?;
+        let duration = stream.list.ended.then(|| stream.list.duration());
+        let tracks = inner
+            .tracks()
+            .iter()
+            .map(|track| {
+                let mut track = track.clone();
+                let tb = track.codec_params.time_base;
+                track.codec_params.n_frames = duration.zip(tb).map(|(d, tb)| tb.calc_timestamp(Time::from(d)));
+                track
+            })
+            .collect();
+        Ok(SegmentReader {
+            stream,
+            inner,
+            tracks,
+            start_ts: 0,
+            offset: None,
+        })
+    }
+
+    /// Segments are added while playing, not seekable.
+    pub fn is_live(&self) -> bool {
+        !self.stream.list.ended
+    }
+}
+
+impl FormatReader for SegmentReader {
+    fn try_new(_source: MediaSourceStream, _options: &FormatOptions) -> Result<Self> {
+        unsupported_error("segments: manifests are opened with SegmentReader::open")
+    }
+
+    fn cues(&self) -> &[Cue] {
+        self.inner.cues()
+    }
+
+    fn metadata(&mut self) -> Metadata<'_> {
+        self.inner.metadata()
+    }
+
+    /// Restart at the segment containing the target time. The returned `actual_ts` is the
+    /// start of that segment.
+    fn seek(&mut self, _mode: SeekMode, to: SeekTo) -> Result<SeekedTo> {
+        let (track_id, tb) = match self.tracks.first() {
+            Some(track) if !self.is_live() => (track.id, track.codec_params.time_base),
+            _ => return seek_error(SeekErrorKind::Unseekable),
+        };
+        let Some(tb) = tb else {
+            return seek_error(SeekErrorKind::Unseekable);
+        };
+        let time = match to {
+            SeekTo::Time { time, .. } => time.seconds as f64 + time.frac,
+            SeekTo::TimeStamp { ts, .. } => ts_seconds(ts, Some(tb)).unwrap_or(0.0),
+        };
+
+        let index = self.stream.list.segment_at(time);
+        self.inner = self
+            .stream
+            .open_reader(index)
+            .map_err(|e| Error::IoError(io::Error::other(e)))?;
+        self.start_ts = tb.calc_timestamp(Time::from(self.stream.list.start_of(index)));
+        self.offset = None;
+        Ok(SeekedTo {
+            track_id,
+            required_ts: tb.calc_timestamp(Time::from(time)),
+            actual_ts: self.start_ts,
+        })
+    }
+
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn next_packet(&mut self) -> Result<Packet> {
+        let mut packet = self.inner.next_packet()?;
+        let offset = *self.offset.get_or_insert(self.start_ts as i64 - packet.ts as i64);
+        packet.ts = packet.ts.saturating_add_signed(offset);
+        Ok(packet)
+    }
+
+    fn into_inner(self: Box<Self>) -> MediaSourceStream {
+        self.inner.into_inner()
+    }
+}
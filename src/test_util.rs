@@ -0,0 +1,282 @@
+//! Helpers shared by tests.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::http::ReconnectPolicy;
+use crate::output::{CapturedAudio, MemorySink};
+use crate::player_engine::PlayerStatus;
+use crate::{Player, PlayerBuilder};
+
+/// Unique path in the system temp dir.
+pub fn temp_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("url2audio-{}-{}-{}", std::process::id(), n, name))
+}
+
+/// Write a 16-bit PCM WAV file. `sample(frame)` gives the value of all channels of a frame.
+pub fn write_wav(path: &Path, rate: u32, channels: u16, frames: u32, sample: impl Fn(u32) -> i16) {
+    std::fs::write(path, wav_bytes(rate, channels, frames, sample)).unwrap();
+}
+
+/// Contents of the file written by `write_wav`.
+pub fn wav_bytes(rate: u32, channels: u16, frames: u32, sample: impl Fn(u32) -> i16) -> Vec<u8> {
+    wav_bytes_tagged(rate, channels, frames, sample, &[])
+}
+
+/// `wav_bytes` with a RIFF INFO list of (id, value) tags, e.g. `(*b"INAM", "Title")`.
+pub fn wav_bytes_tagged(
+    rate: u32,
+    channels: u16,
+    frames: u32,
+    sample: impl Fn(u32) -> i16,
+    tags: &[([u8; 4], &str)],
+) -> Vec<u8> {
+    let mut info = b"INFO".to_vec();
+    for (id, value) in tags {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        info.extend_from_slice(id);
+        info.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        if value.len() % 2 == 1 {
+            value.push(0);
+        }
+        info.extend_from_slice(&value);
+    }
+
+    let data_len = frames * channels as u32 * 2;
+    let list_len = if tags.is_empty() { 0 } else { 8 + info.len() as u32 };
+    let mut wav = Vec::with_capacity(44 + list_len as usize + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + list_len + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&rate.to_le_bytes());
+    wav.extend_from_slice(&(rate * channels as u32 * 2).to_le_bytes());
+    wav.extend_from_slice(&(channels * 2).to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    if !tags.is_empty() {
+        wav.extend_from_slice(b"LIST");
+        wav.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&info);
+    }
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for i in 0..frames {
+        for _ in 0..channels {
+            wav.extend_from_slice(&sample(i).to_le_bytes());
+        }
+    }
+    wav
+}
+
+/// Init section of fragmented MP4 with one mono 16-bit PCM (`sowt`) track at `rate`.
+pub fn fmp4_init(rate: u32) -> Vec<u8> {
+    let matrix = [0x10000u32, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000].map(u32::to_be_bytes).concat();
+
+    let mut mvhd = vec![0; 12];
+    mvhd.extend_from_slice(&rate.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 4]);
+    mvhd.extend_from_slice(&[0, 1, 0, 0, 1, 0]);
+    mvhd.extend_from_slice(&[0; 10]);
+    mvhd.extend_from_slice(&matrix);
+    mvhd.extend_from_slice(&[0; 24]);
+    mvhd.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut tkhd = vec![0; 12];
+    tkhd.extend_from_slice(&1u32.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 20]);
+    tkhd.extend_from_slice(&[1, 0, 0, 0]);
+    tkhd.extend_from_slice(&matrix);
+    tkhd.extend_from_slice(&[0; 8]);
+
+    let mut mdhd = vec![0; 12];
+    mdhd.extend_from_slice(&rate.to_be_bytes());
+    mdhd.extend_from_slice(&[0; 4]);
+    mdhd.extend_from_slice(&[0x55, 0xc4, 0, 0]);
+
+    let mut hdlr = vec![0; 8];
+    hdlr.extend_from_slice(b"soun");
+    hdlr.extend_from_slice(&[0; 12]);
+    hdlr.extend_from_slice(b"Sound\0");
+
+    let mut sowt = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    sowt.extend_from_slice(&[0; 8]);
+    sowt.extend_from_slice(&[0, 1, 0, 16, 0, 0, 0, 0]);
+    sowt.extend_from_slice(&(rate << 16).to_be_bytes());
+    let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    stsd.extend_from_slice(&atom(b"sowt", &[&sowt]));
+
+    let stbl = atom(
+        b"stbl",
+        &[
+            &atom(b"stsd", &[&stsd]),
+            &atom(b"stts", &[&[0; 8]]),
+            &atom(b"stsc", &[&[0; 8]]),
+            &atom(b"stsz", &[&[0; 12]]),
+            &atom(b"stco", &[&[0; 8]]),
+        ],
+    );
+    let minf = atom(b"minf", &[&atom(b"smhd", &[&[0; 8]]), &stbl]);
+    let mdia = atom(b"mdia", &[&atom(b"mdhd", &[&mdhd]), &atom(b"hdlr", &[&hdlr]), &minf]);
+    let trak = atom(b"trak", &[&atom(b"tkhd", &[&tkhd]), &mdia]);
+
+    // One sample per frame: default duration 1, size 2.
+    let trex = [0u32, 1, 1, 1, 2, 0].map(u32::to_be_bytes).concat();
+    let mvex = atom(b"mvex", &[&atom(b"trex", &[&trex])]);
+
+    let mut init = atom(b"ftyp", &[b"isom", &[0, 0, 2, 0], b"isomiso6"]);
+    init.extend_from_slice(&atom(b"moov", &[&atom(b"mvhd", &[&mvhd]), &trak, &mvex]));
+    init
+}
+
+/// Media segment (moof + mdat) of `fmp4_init` with `samples`, decoded from `base_ts` (`tfdt`).
+pub fn fmp4_segment(sequence: u32, base_ts: u64, samples: &[i16]) -> Vec<u8> {
+    let mfhd = atom(b"mfhd", &[&[0; 4], &sequence.to_be_bytes()]);
+    // Sample data follows the moof, addressed relative to its start.
+    let tfhd = atom(b"tfhd", &[&[0, 2, 0, 0], &1u32.to_be_bytes()]);
+    let tfdt = atom(b"tfdt", &[&[1, 0, 0, 0], &base_ts.to_be_bytes()]);
+    let moof_len = 8 + mfhd.len() + 8 + tfhd.len() + tfdt.len() + 20;
+    let trun = atom(
+        b"trun",
+        &[&[0, 0, 0, 1], &(samples.len() as u32).to_be_bytes(), &(moof_len as u32 + 8).to_be_bytes()],
+    );
+    let mut segment = atom(b"moof", &[&mfhd, &atom(b"traf", &[&tfhd, &tfdt, &trun])]);
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    segment.extend_from_slice(&atom(b"mdat", &[&data]));
+    segment
+}
+
+/// MP4 atom of `kind` with the concatenated `parts` as body.
+fn atom(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+    let len: usize = 8 + parts.iter().map(|p| p.len()).sum::<usize>();
+    let mut atom = (len as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    for part in parts {
+        atom.extend_from_slice(part);
+    }
+    atom
+}
+
+/// Wait for a player event matching `pred`, returns false on timeout.
+pub fn wait_for(player: &Player, pred: impl Fn(&PlayerStatus) -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match player.events().recv_timeout(left) {
+            Ok(event) if pred(&event) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// Player with a `MemorySink`, built by `configure`, and the audio it captures.
+pub fn memory_player(
+    configure: impl FnOnce(PlayerBuilder) -> PlayerBuilder,
+) -> (Player, Arc<Mutex<CapturedAudio>>) {
+    let sink = MemorySink::new();
+    let captured = sink.captured();
+    (configure(Player::builder().sink(sink)).build(), captured)
+}
+
+/// Serve `wav` over HTTP with range support and play it on a `memory_player`.
+pub fn play_wav_with(
+    wav: Vec<u8>,
+    configure: impl FnOnce(PlayerBuilder) -> PlayerBuilder,
+) -> (Player, Arc<Mutex<CapturedAudio>>) {
+    let url = http_server(move |req| range_response(&wav, req));
+    let (mut player, captured) = memory_player(configure);
+    player.open(&format!("{}/audio.wav", url));
+    (player, captured)
+}
+
+/// Reconnect policy with short delays.
+pub fn fast_reconnect() -> ReconnectPolicy {
+    ReconnectPolicy {
+        max_retries: 3,
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+    }
+}
+
+/// Request received by `http_server`.
+pub struct Request {
+    pub path: String,
+    /// Header names are lower case.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Serve HTTP on localhost, `handler` returns the raw response of a request.
+/// Connections are served concurrently. Returns the base url, e.g. `http://127.0.0.1:1234`.
+pub fn http_server(handler: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static) -> String {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = std::sync::Arc::new(handler);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let handler = handler.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                let _ = reader.read_line(&mut line);
+                let path = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let mut headers = Vec::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                    }
+                }
+                let _ = stream.write_all(&handler(&Request { path, headers }));
+            });
+        }
+    });
+    url
+}
+
+/// Response to a request for `body`, honouring an open ended `Range: bytes=<start>-`.
+pub fn range_response(body: &[u8], req: &Request) -> Vec<u8> {
+    let ranges = ("accept-ranges", "bytes".to_string());
+    let start = req
+        .header("range")
+        .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+    match start {
+        Some(start) if start < body.len() => {
+            let len = ("content-length", (body.len() - start).to_string());
+            let range = ("content-range", format!("bytes {}-{}/{}", start, body.len() - 1, body.len()));
+            http_response(206, &[len, ranges, range], &body[start..])
+        }
+        Some(_) => http_response(416, &[], b""),
+        None => http_response(200, &[("content-length", body.len().to_string()), ranges], body),
+    }
+}
+
+/// Raw HTTP/1.1 response, the connection is closed after the body.
+pub fn http_response(status: u16, headers: &[(&str, String)], body: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {} X\r\nConnection: close\r\n", status);
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
@@ -1,109 +1,451 @@
-use std::{collections::BTreeMap, io::{self, Read, Seek, SeekFrom}};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
 use symphonia::core::io::MediaSource;
-use crossbeam_channel::Sender;
 
-use crate::player_engine::PlayerStatus;
+use crate::cache::{CacheEntry, Validators};
+use crate::http::{is_retryable, HttpClient, HttpResponse, ReadAhead, ReconnectPolicy, SourceOptions};
+use crate::icy::{IcyReader, StreamInfo};
+use crate::player_engine::{PlayerStatus, StatusLink};
 use crate::Url2AudioError;
 
-const CHUNK_SIZE: usize = 65536;
+pub(crate) const CHUNK_SIZE: usize = 65536;
 const MAX_CHUNK_DISTANCE: usize = 32;
+/// A missing chunk at most this many chunks ahead of the download is waited for,
+/// chunks further away are requested with a new Range request.
+const MAX_SKIP_CHUNKS: usize = 2;
+/// Bytes per second assumed for `ReadAhead::Seconds` if the bitrate is unknown (128 kbit/s).
+const DEFAULT_BYTE_RATE: f64 = 16000.0;
+/// Reading may block when less than this is downloaded ahead of the read position
+/// (largest block symphonia reads from a source at once).
+const STALL_BYTES: usize = 32 * 1024;
 
 /// Wrapper which impl `Read`, `Seek`, `Send`, `Sync` and `MediaSource`
-/// for reader returned by `ureq` request.
+/// for a stream fetched with `ureq` requests.
+///
+/// A background worker downloads chunks ahead of the read position, so reads only block
+/// when the chunk at the read position hasn't been downloaded yet. With a disk cache,
+/// stored chunks are read from it instead of the network.
 pub struct UrlSourceBuf {
-    chunks: BTreeMap<usize, Vec<u8>>,
-    url: String,
-    reader: Box<dyn Read + Sync + Send>,
+    shared: Arc<Shared>,
     pos: usize,
     len: Option<u64>,
-    tx: Option<crossbeam_channel::Sender<PlayerStatus>>
+    /// Live stream: no Range requests, reads continue the initial response.
+    live: bool,
+    /// `Content-Type` of the response.
+    content_type: Option<String>,
+}
+
+/// Handle to adjust the read-ahead of a `UrlSourceBuf` once the stream's bitrate is known.
+#[derive(Clone)]
+pub struct Prefetch(Arc<Shared>);
+
+struct Shared {
+    state: Mutex<State>,
+    /// Notified when a chunk is downloaded, the download fails or the read position moves.
+    changed: Condvar,
+}
+
+struct State {
+    chunks: BTreeMap<usize, Vec<u8>>,
+    /// Chunks stored in the disk cache.
+    cached: BTreeSet<usize>,
+    /// Read position of the decoder.
+    pos: usize,
+    /// Chunk the worker downloads next.
+    next: usize,
+    /// Chunk the worker should reopen the connection at.
+    reposition: Option<usize>,
+    read_ahead: ReadAhead,
+    byte_rate: f64,
+    /// Length of the stream, from content-length or once the response ended.
+    end: Option<usize>,
+    /// Download failure, returned by the next read of a missing chunk.
+    error: Option<io::Error>,
+    /// Source dropped, worker exits.
+    closed: bool,
+}
+
+type BodyReader = Box<dyn Read + Sync + Send>;
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl State {
+    fn read_ahead_chunks(&self) -> usize {
+        self.read_ahead.bytes(self.byte_rate).div_ceil(CHUNK_SIZE).max(1)
+    }
+
+    /// Bytes downloaded contiguously from the read position, `None` if the download reached
+    /// the end of the stream or failed, so reads don't wait anymore.
+    fn buffered(&self) -> Option<usize> {
+        if self.error.is_some() {
+            return None;
+        }
+        let mut key = self.pos / CHUNK_SIZE;
+        let mut buffered = 0;
+        while let Some(len) = self.chunk_len(key) {
+            buffered += len;
+            key += 1;
+        }
+        let buffered = buffered.saturating_sub(self.pos % CHUNK_SIZE);
+        match self.end {
+            Some(end) if self.pos + buffered >= end => None,
+            _ => Some(buffered),
+        }
+    }
+
+    /// Length of chunk `key` if it is downloaded or in the disk cache.
+    fn chunk_len(&self, key: usize) -> Option<usize> {
+        if let Some(chunk) = self.chunks.get(&key) {
+            return Some(chunk.len());
+        }
+        // Only sources with a known length are cached.
+        let end = self.end.filter(|_| self.cached.contains(&key))?;
+        Some(CHUNK_SIZE.min(end.saturating_sub(key * CHUNK_SIZE)))
+    }
+
+    fn evict_distant_chunks(&mut self) {
+        let current_key = self.pos / CHUNK_SIZE;
+        let min = current_key.saturating_sub(MAX_CHUNK_DISTANCE);
+        let max = current_key + MAX_CHUNK_DISTANCE.max(self.read_ahead_chunks());
+        self.chunks.retain(|&k, _| k >= min && k <= max);
+    }
 }
 
 impl UrlSourceBuf {
-    pub fn new(url: &str, tx: Option<Sender<PlayerStatus>>) -> Result<Self, Url2AudioError> {
-        let r = ureq::get(url).call()?;
+    pub fn new(url: &str, tx: Option<StatusLink>, options: &SourceOptions) -> Result<Self, Url2AudioError> {
+        let client = HttpClient::new(&options.http, options.transport.clone())?;
+        let r = client.get(url, None, options.http.icy_headers())?;
+        let info = StreamInfo::from_headers(|name| r.header(name));
+        let byte_rate = info
+            .as_ref()
+            .and_then(|info| info.bitrate)
+            .map_or(DEFAULT_BYTE_RATE, |kbps| kbps as f64 * 125.0);
+        if let (Some(info), Some(tx)) = (info, &tx) {
+            tx.send(PlayerStatus::StreamInfo(info));
+        }
+        // Only interleaved metadata makes a stream ICY, some servers of plain files send
+        // `icy-name` and the like as well. With it content-length is not the length of the audio.
+        let icy = r.header("icy-metaint").is_some();
         let len = r.header("content-length")
-            .and_then(|s| s.parse::<u64>().ok());
-        let reader = r.into_reader();
-        Ok(UrlSourceBuf {
-            chunks: Default::default(),
-            reader: Box::new(reader),
+            .and_then(|s| s.parse::<u64>().ok())
+            .filter(|_| !icy);
+        let live = is_live(len, icy);
+        // Caching is best effort, sources play without it if the cache can't be opened.
+        let cache = match (&options.cache, len) {
+            (Some(cache), Some(len)) if !live => {
+                let validators = Validators {
+                    etag: r.header("etag").map(str::to_string),
+                    last_modified: r.header("last-modified").map(str::to_string),
+                    len,
+                };
+                cache.open(url, validators).ok()
+            }
+            _ => None,
+        };
+        let cached: BTreeSet<usize> = cache.iter().flat_map(|entry| entry.chunks()).collect();
+        let content_type = r.header("content-type").map(str::to_string);
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                chunks: Default::default(),
+                cached: cached.clone(),
+                pos: 0,
+                next: 0,
+                reposition: None,
+                read_ahead: options.read_ahead.clone(),
+                byte_rate,
+                end: len.map(|len| len as usize),
+                error: None,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let worker = Worker {
+            shared: shared.clone(),
+            reader: body_reader(r, tx.clone()),
+            reader_at: Some(0),
+            cache,
+            client,
             url: url.to_string(),
-            pos: 0,
+            icy_headers: options.http.icy_headers(),
+            len,
+            live,
+            reconnect: options.reconnect.clone(),
             tx,
+        };
+        // Cached ranges are shown right away.
+        for &key in &cached {
+            worker.report_chunk(key);
+        }
+        std::thread::spawn(move || worker.run());
+
+        Ok(UrlSourceBuf {
+            shared,
+            pos: 0,
             len,
+            live,
+            content_type,
         })
     }
 
-    fn get_chunk_key(&self, p: usize) -> usize {
-        p / CHUNK_SIZE
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
     }
 
-    fn evict_distant_chunks(&mut self) {
-        let current_key = self.get_chunk_key(self.pos);
-        let min = current_key.saturating_sub(MAX_CHUNK_DISTANCE);
-        let max = current_key + MAX_CHUNK_DISTANCE;
-        self.chunks.retain(|&k, _| k >= min && k <= max);
+    pub fn prefetch(&self) -> Prefetch {
+        Prefetch(self.shared.clone())
+    }
+}
+
+impl Prefetch {
+    /// Set the bitrate `ReadAhead::Seconds` is converted with.
+    pub fn set_byte_rate(&self, byte_rate: f64) {
+        if byte_rate.is_finite() && byte_rate > 0.0 {
+            self.0.lock().byte_rate = byte_rate;
+            self.0.changed.notify_all();
+        }
+    }
+
+    /// Downloaded part (0.0 - 1.0) of the next `secs` of the stream from the read position,
+    /// at most the read-ahead. 1.0 when the download reached the end or failed.
+    pub fn fill(&self, secs: f64) -> f32 {
+        let state = self.0.lock();
+        let Some(buffered) = state.buffered() else {
+            return 1.0;
+        };
+        let wanted = ((secs.max(0.0) * state.byte_rate) as usize).min(state.read_ahead_chunks() * CHUNK_SIZE);
+        if wanted == 0 {
+            return 1.0;
+        }
+        (buffered as f64 / wanted as f64).min(1.0) as f32
+    }
+
+    /// The next reads may block waiting for the download.
+    pub fn stalled(&self) -> bool {
+        self.0.lock().buffered().is_some_and(|buffered| buffered < STALL_BYTES)
+    }
+}
+
+impl Drop for UrlSourceBuf {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Downloads chunks in background. Owns the HTTP connection.
+struct Worker {
+    shared: Arc<Shared>,
+    reader: BodyReader,
+    /// Chunk the connection delivers next, `None` if it has to be reopened.
+    reader_at: Option<usize>,
+    cache: Option<CacheEntry>,
+    client: HttpClient,
+    url: String,
+    icy_headers: &'static [(&'static str, &'static str)],
+    len: Option<u64>,
+    live: bool,
+    reconnect: ReconnectPolicy,
+    tx: Option<StatusLink>,
+}
+
+impl Worker {
+    fn run(mut self) {
+        while let Some(key) = self.next_chunk() {
+            let result = match self.cached_chunk(key) {
+                Some(chunk) => Ok(chunk),
+                None => self.download_chunk(key),
+            };
+
+            let mut state = self.shared.lock();
+            match result {
+                Ok(chunk) => {
+                    if chunk.len() < CHUNK_SIZE {
+                        state.end = Some(key * CHUNK_SIZE + chunk.len());
+                    }
+                    if self.cache.as_ref().is_some_and(|cache| cache.contains(key)) {
+                        state.cached.insert(key);
+                    }
+                    state.chunks.insert(key, chunk);
+                    if state.reposition.is_none() {
+                        state.next = key + 1;
+                    }
+                }
+                Err(e) => state.error = Some(e),
+            }
+            self.shared.changed.notify_all();
+        }
     }
 
-    fn has_chunk(&self, key: usize) -> bool {
-        self.chunks.contains_key(&key)
+    /// Chunk `key` from the disk cache, if stored.
+    fn cached_chunk(&mut self, key: usize) -> Option<Vec<u8>> {
+        let cache = self.cache.as_mut().filter(|cache| cache.contains(key))?;
+        // Unreadable cache falls back to the network.
+        cache.read_chunk(key).ok()
     }
 
-    fn insert_chunk(&mut self, chunk_key: usize, chunk: Vec<u8>) {
+    /// Download chunk `key`, reopening the connection unless it is positioned at the chunk.
+    /// Downloaded chunks are stored in the disk cache and reported.
+    fn download_chunk(&mut self, key: usize) -> io::Result<Vec<u8>> {
+        if self.reader_at != Some(key) {
+            self.reader_at = None;
+            self.reader = self.open_reader(key * CHUNK_SIZE).map_err(io::Error::other)?;
+        }
+        let chunk = self.fetch_chunk(key);
+        self.reader_at = chunk.as_ref().ok().map(|_| key + 1);
+        if let Ok(chunk) = &chunk {
+            if let Some(cache) = self.cache.as_mut() {
+                let _ = cache.write_chunk(key, chunk);
+            }
+            self.report_chunk(key);
+        }
+        chunk
+    }
+
+    /// Wait until a chunk should be downloaded. Returns its key, `None` when the source
+    /// was dropped.
+    fn next_chunk(&self) -> Option<usize> {
+        let mut state = self.shared.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(key) = state.reposition.take() {
+                state.next = key;
+                state.error = None;
+                return Some(key);
+            }
+            let begin = state.next * CHUNK_SIZE;
+            let done = state.error.is_some()
+                || state.end.is_some_and(|end| begin >= end)
+                || self.len.is_some_and(|len| begin as u64 >= len);
+            if !done && state.next <= state.pos / CHUNK_SIZE + state.read_ahead_chunks() {
+                return Some(state.next);
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+
+    fn report_chunk(&self, chunk_key: usize) {
+        if let (Some(tx), Some(l)) = (self.tx.as_ref(), self.len) {
+            let start = chunk_key as f32 * CHUNK_SIZE as f32 / l as f32;
+            let end = start + CHUNK_SIZE as f32 / l as f32;
+            tx.send(PlayerStatus::ChunkAdded(start, end));
+        }
+    }
+
+    /// Request the stream from byte `pos`. Live streams continue from the current point.
+    /// ICY metadata is requested only for live streams, so byte positions of Range
+    /// requests are the ones of the audio.
+    fn open_reader(&self, pos: usize) -> Result<BodyReader, Url2AudioError> {
+        if self.live {
+            let r = self.client.get(&self.url, None, self.icy_headers)?;
+            return Ok(body_reader(r, self.tx.clone()));
+        }
+        let r = self.client.get(&self.url, Some(pos as u64), &[])?;
+        if r.status == 206 || pos == 0 {
+            return Ok(r.body);
+        }
+        // The server ignored the range and sends the whole stream, skip to `pos`.
+        let mut body = r.body;
+        let skipped = io::copy(&mut body.by_ref().take(pos as u64), &mut io::sink())?;
+        if skipped < pos as u64 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream shorter than its content-length").into());
+        }
+        Ok(body)
+    }
+
+    /// Replace the failed reader with a new request from `pos`, waiting with exponential
+    /// backoff between attempts. `attempt` counts attempts made for the current chunk.
+    /// Returns `err` when the retry budget is exhausted.
+    fn reconnect(&mut self, pos: usize, attempt: &mut u32, err: io::Error) -> io::Result<()> {
+        loop {
+            *attempt += 1;
+            if *attempt > self.reconnect.max_retries || self.shared.lock().closed {
+                return Err(err);
+            }
+            self.send(PlayerStatus::Reconnecting(*attempt));
+            std::thread::sleep(self.reconnect.delay(*attempt));
+            match self.open_reader(pos) {
+                Ok(reader) => {
+                    self.reader = reader;
+                    self.send(PlayerStatus::Reconnected);
+                    return Ok(());
+                }
+                Err(Url2AudioError::Status(code)) if !is_retryable(code) => {
+                    return Err(io::Error::other(Url2AudioError::Status(code)))
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn send(&self, status: PlayerStatus) {
         if let Some(tx) = self.tx.as_ref() {
-            self.len = self.len.or_else(|| self.byte_len());
-            if let Some(l) = self.len {
-                let start = chunk_key as f32 * CHUNK_SIZE as f32 / l as f32;
-                let end = start + CHUNK_SIZE as f32 / l as f32;
-                let _ = tx.try_send(PlayerStatus::ChunkAdded(start, end));
-            }
-        }
-        self.chunks.insert(chunk_key, chunk);
-    }
-
-    /// Reopen the HTTP reader from the chunk boundary of the current `pos`,
-    /// using an open-ended Range so subsequent sequential reads keep working.
-    /// Pre-fetches and caches the first chunk if not already present.
-    fn reposition_reader(&mut self) -> io::Result<()> {
-        let chunk_key = self.get_chunk_key(self.pos);
-        let chunk_begin = chunk_key * CHUNK_SIZE;
-        let res = ureq::get(&self.url)
-            .set("Range", &format!("bytes={}-", chunk_begin))
-            .call()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        self.reader = Box::new(res.into_reader());
-        if !self.has_chunk(chunk_key) {
-            let chunk = Self::read_chunk_from_reader(&mut self.reader)?;
-            self.insert_chunk(chunk_key, chunk);
-        }
-        Ok(())
-    }
-
-    /// Read up to CHUNK_SIZE bytes from `reader` into a heap-allocated Vec.
-    /// Tolerates short reads (e.g. at EOF) — returned Vec is always CHUNK_SIZE,
-    /// zero-padded if fewer bytes are available.
-    fn read_chunk_from_reader(reader: &mut Box<dyn Read + Sync + Send>) -> io::Result<Vec<u8>> {
+            tx.send(status);
+        }
+    }
+
+    /// Read chunk `chunk_key` from the current reader, which is positioned at its start.
+    /// Returned Vec is shorter than CHUNK_SIZE only if the stream ended. Failed reads and
+    /// connections closed before content-length are resumed according to `reconnect`.
+    fn fetch_chunk(&mut self, chunk_key: usize) -> io::Result<Vec<u8>> {
+        let begin = chunk_key * CHUNK_SIZE;
         let mut buf = vec![0u8; CHUNK_SIZE];
         let mut bytes_read = 0;
-        while bytes_read < CHUNK_SIZE {
-            match reader.read(&mut buf[bytes_read..]) {
-                Ok(0) => break, // EOF — zero-pad the rest
-                Ok(n) => bytes_read += n,
-                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e),
-            }
+        let mut attempt = 0;
+        loop {
+            let err = match fill(&mut self.reader, &mut buf, &mut bytes_read) {
+                Ok(()) if bytes_read == CHUNK_SIZE => break,
+                // A live stream ending is the end of the broadcast.
+                Ok(()) if self.live || self.len.is_none_or(|len| (begin + bytes_read) as u64 >= len) => break,
+                Ok(()) => io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before end of stream"),
+                Err(e) => e,
+            };
+            self.reconnect(begin + bytes_read, &mut attempt, err)?;
         }
+        buf.truncate(bytes_read);
         Ok(buf)
     }
 }
 
-unsafe impl Send for UrlSourceBuf {}
-unsafe impl Sync for UrlSourceBuf {}
+/// Read from `reader` into `buf[*bytes_read..]` until it is full or the reader ends.
+fn fill(reader: &mut BodyReader, buf: &mut [u8], bytes_read: &mut usize) -> io::Result<()> {
+    while *bytes_read < buf.len() {
+        match reader.read(&mut buf[*bytes_read..]) {
+            Ok(0) => break, // EOF
+            Ok(n) => *bytes_read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Body of a response, without ICY metadata if the server interleaves it.
+fn body_reader(r: HttpResponse, tx: Option<StatusLink>) -> BodyReader {
+    match r.header("icy-metaint").and_then(|s| s.parse::<usize>().ok()).filter(|&m| m > 0) {
+        Some(metaint) => Box::new(IcyReader::new(r.body, metaint, tx)),
+        None => r.body,
+    }
+}
+
+/// Internet radio streams have no length or interleave ICY metadata. `Accept-Ranges` is
+/// only advisory, servers leaving it out may still honour ranges; if they don't, the start
+/// of the response is skipped instead.
+fn is_live(content_length: Option<u64>, icy: bool) -> bool {
+    content_length.is_none() || icy
+}
 
 impl MediaSource for UrlSourceBuf {
     fn is_seekable(&self) -> bool {
-        true
+        !self.live
     }
 
     fn byte_len(&self) -> Option<u64> {
@@ -111,52 +453,373 @@ impl MediaSource for UrlSourceBuf {
     }
 }
 
-
 impl Read for UrlSourceBuf {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.len.is_some_and(|len| self.pos as u64 >= len) {
+            return Ok(0);
+        }
+
         let chunk_key = self.pos / CHUNK_SIZE;
         let offset = self.pos % CHUNK_SIZE;
 
-        if !self.has_chunk(chunk_key) {
-            let chunk = Self::read_chunk_from_reader(&mut self.reader)?;
-            self.insert_chunk(chunk_key, chunk);
+        let mut state = self.shared.lock();
+        if state.pos != self.pos {
+            // Read position moved (seek), the worker may have to download other chunks.
+            state.pos = self.pos;
+            self.shared.changed.notify_all();
         }
+        let bytes_to_read = loop {
+            if let Some(chunk) = state.chunks.get(&chunk_key) {
+                // Chunk is shorter than CHUNK_SIZE only at the end of the stream.
+                let bytes_to_read = buf.len().min(chunk.len().saturating_sub(offset));
+                buf[..bytes_to_read].copy_from_slice(&chunk[offset..offset + bytes_to_read]);
+                break bytes_to_read;
+            }
+            if state.end.is_some_and(|end| self.pos >= end) {
+                return Ok(0);
+            }
+            if let Some(e) = state.error.take() {
+                // The worker waits while the error is set, the next read retries the chunk.
+                self.shared.changed.notify_all();
+                return Err(e);
+            }
+            let upcoming = chunk_key >= state.next && chunk_key <= state.next + MAX_SKIP_CHUNKS;
+            if !upcoming && !self.live && state.reposition != Some(chunk_key) {
+                state.reposition = Some(chunk_key);
+                self.shared.changed.notify_all();
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        };
 
-        let chunk = self.chunks.get(&chunk_key).unwrap();
-        let bytes_to_read = buf.len().min(CHUNK_SIZE - offset);
-        buf[..bytes_to_read].copy_from_slice(&chunk[offset..offset + bytes_to_read]);
         self.pos += bytes_to_read;
-        self.evict_distant_chunks();
+        state.pos = self.pos;
+        if self.pos / CHUNK_SIZE != chunk_key {
+            state.evict_distant_chunks();
+            self.shared.changed.notify_all();
+        }
         Ok(bytes_to_read)
     }
 }
 
 impl Seek for UrlSourceBuf {
+    /// Only moves the read position, chunks are requested by the following reads.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        match pos {
-            SeekFrom::Start(p) => {
-                self.pos = p as usize;
-                self.reposition_reader()?;
-                Ok(p)
-            },
+        let new_pos = match pos {
+            SeekFrom::Start(p) => p as i64,
             SeekFrom::End(p) => {
                 let total_len = self.byte_len()
-                    .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no content-length available"))?;
-                let new_pos = (total_len as i64 + p).max(0) as usize;
-                self.pos = new_pos;
-                self.reposition_reader()?;
-                Ok(new_pos as u64)
-            },
-            SeekFrom::Current(p) => {
-                let new_pos = (self.pos as i64 + p).max(0) as usize;
-                self.pos = new_pos;
-                self.reposition_reader()?;
-                Ok(new_pos as u64)
-            },
+                    .ok_or_else(|| io::Error::other("no content-length available"))?;
+                total_len as i64 + p
+            }
+            SeekFrom::Current(p) => self.pos as i64 + p,
+        };
+        let new_pos = new_pos.max(0) as usize;
+        if self.live && new_pos != self.pos {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "live stream is not seekable"));
         }
+        self.pos = new_pos;
+        Ok(new_pos as u64)
     }
 }
 
+#[test]
+fn live_stream_detection() {
+    assert!(!is_live(Some(1000), false));
+    assert!(is_live(None, false));
+    assert!(is_live(Some(1000), true));
+}
+
+#[test]
+fn seeks_without_range_support() {
+    use crate::test_util::{http_response, http_server};
+
+    // No `Accept-Ranges`, and Range requests are answered with the whole body.
+    let body: Vec<u8> = (0..10 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let url = http_server(move |_| http_response(200, &[("content-length", body.len().to_string())], &body));
+    let options = SourceOptions {
+        read_ahead: ReadAhead::Bytes(CHUNK_SIZE),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new(&format!("{}/episode.mp3", url), None, &options).unwrap();
+    assert!(source.is_seekable());
+    let pos = 8 * CHUNK_SIZE + 5;
+    source.seek(SeekFrom::Start(pos as u64)).unwrap();
+    let mut buf = vec![0u8; 100];
+    source.read_exact(&mut buf).unwrap();
+    assert!(buf.iter().enumerate().all(|(i, &b)| b == ((pos + i) % 251) as u8));
+}
+
+#[test]
+fn icy_headers_without_metaint_are_not_live() {
+    use crate::http::HttpConfig;
+    use crate::test_util::{http_response, http_server};
+
+    let body: Vec<u8> = (0..2 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let icy_requested = Arc::new(Mutex::new(Vec::new()));
+    let url = {
+        let icy_requested = icy_requested.clone();
+        http_server(move |req| {
+            icy_requested.lock().unwrap().push(req.header("icy-metadata").is_some());
+            // Podcast CDNs may announce the show like a radio station.
+            let headers = [
+                ("content-length", body.len().to_string()),
+                ("accept-ranges", "bytes".to_string()),
+                ("icy-name", "Podcast".to_string()),
+            ];
+            http_response(200, &headers, &body)
+        })
+    };
+    let url = format!("{}/episode.mp3", url);
+    let source = UrlSourceBuf::new(&url, None, &SourceOptions::default()).unwrap();
+    assert!(source.is_seekable());
+    assert_eq!(source.byte_len(), Some(2 * CHUNK_SIZE as u64));
+
+    let options = SourceOptions {
+        http: HttpConfig::new().icy_metadata(false),
+        ..Default::default()
+    };
+    UrlSourceBuf::new(&url, None, &options).unwrap();
+    assert_eq!(*icy_requested.lock().unwrap(), [true, false]);
+}
+
+#[test]
+fn prefetches_read_ahead_and_seeks() {
+    use crate::test_util::{http_server, range_response};
+
+    let body: Vec<u8> = (0..10 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let url = {
+        let ranges = ranges.clone();
+        http_server(move |req| {
+            ranges.lock().unwrap().push(req.header("range").map(str::to_string));
+            range_response(&body, req)
+        })
+    };
+    let options = SourceOptions {
+        read_ahead: ReadAhead::Bytes(3 * CHUNK_SIZE),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new(&format!("{}/stream", url), None, &options).unwrap();
+
+    // Without any read the worker downloads the current chunk and 3 ahead, then waits.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while source.shared.lock().chunks.len() < 4 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let keys: Vec<usize> = source.shared.lock().chunks.keys().copied().collect();
+    assert_eq!(keys, [0, 1, 2, 3]);
+
+    // A far seek reopens the connection at the target chunk.
+    let pos = 8 * CHUNK_SIZE + 5;
+    source.seek(SeekFrom::Start(pos as u64)).unwrap();
+    let mut buf = vec![0u8; 100];
+    source.read_exact(&mut buf).unwrap();
+    assert!(buf.iter().enumerate().all(|(i, &b)| b == ((pos + i) % 251) as u8));
+    let range = format!("bytes={}-", 8 * CHUNK_SIZE);
+    assert!(ranges.lock().unwrap().contains(&Some(range)));
+}
+
+#[test]
+fn retries_throttled_reconnects() {
+    use crate::test_util::{fast_reconnect, http_response, http_server, range_response};
+
+    let body: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let requests = Arc::new(Mutex::new(0));
+    let url = {
+        let (requests, body) = (requests.clone(), body.clone());
+        http_server(move |req| {
+            let mut requests = requests.lock().unwrap();
+            *requests += 1;
+            match *requests {
+                // Connection lost in the second chunk, then the CDN is under load.
+                1 => http_response(200, &[("content-length", body.len().to_string())], &body[..CHUNK_SIZE + 5]),
+                2 => http_response(429, &[], b""),
+                _ => range_response(&body, req),
+            }
+        })
+    };
+    let options = SourceOptions {
+        reconnect: fast_reconnect(),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new(&format!("{}/episode.mp3", url), None, &options).unwrap();
+    let mut read = Vec::new();
+    source.read_to_end(&mut read).unwrap();
+    assert_eq!(read, body);
+    assert_eq!(*requests.lock().unwrap(), 3);
+}
+
+#[test]
+fn reads_again_after_error() {
+    use crate::test_util::{http_response, http_server, range_response};
+
+    let body: Vec<u8> = (0..3 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let requests = Arc::new(Mutex::new(0));
+    let url = {
+        let (requests, body) = (requests.clone(), body.clone());
+        http_server(move |req| {
+            let mut requests = requests.lock().unwrap();
+            *requests += 1;
+            match *requests {
+                // Connection lost in the second chunk.
+                1 => http_response(200, &[("content-length", body.len().to_string())], &body[..CHUNK_SIZE + 5]),
+                _ => range_response(&body, req),
+            }
+        })
+    };
+    let options = SourceOptions {
+        reconnect: ReconnectPolicy::disabled(),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new(&format!("{}/episode.mp3", url), None, &options).unwrap();
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        source.read_exact(&mut buf).unwrap();
+        // The worker failed and waits.
+        while source.shared.lock().error.is_none() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(source.read_exact(&mut buf).is_err());
+        // Reading the failed chunk again downloads it instead of waiting forever.
+        let _ = tx.send(source.read_exact(&mut buf).map(|_| buf));
+    });
+    let chunk = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(chunk, body[CHUNK_SIZE..2 * CHUNK_SIZE]);
+}
+
+#[test]
+fn serves_cached_chunks_without_download() {
+    use crate::cache::DiskCache;
+    use crate::test_util::{http_server, range_response, temp_path};
+
+    let body: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let url = {
+        let body = body.clone();
+        let ranges = ranges.clone();
+        http_server(move |req| {
+            ranges.lock().unwrap().push(req.header("range").map(str::to_string));
+            range_response(&body, req)
+        })
+    };
+    let url = format!("{}/episode.mp3", url);
+    let dir = temp_path("source-cache");
+    let options = SourceOptions {
+        cache: Some(DiskCache::new(&dir, 100 * CHUNK_SIZE as u64)),
+        ..Default::default()
+    };
+
+    let mut data = Vec::new();
+    UrlSourceBuf::new(&url, None, &options).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, body);
+    ranges.lock().unwrap().clear();
+
+    // Cached ranges are reported on open, only the initial request goes to the server.
+    let link = StatusLink::default();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    link.connect(tx);
+    let mut source = UrlSourceBuf::new(&url, Some(link), &options).unwrap();
+    let reported = rx.try_iter().filter(|s| matches!(s, PlayerStatus::ChunkAdded(..))).count();
+    assert_eq!(reported, 4);
+    let mut data = Vec::new();
+    source.read_to_end(&mut data).unwrap();
+    assert_eq!(data, body);
+    assert_eq!(*ranges.lock().unwrap(), [None]);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn applies_http_config_to_all_requests() {
+    use crate::http::HttpConfig;
+    use crate::test_util::{http_server, range_response};
+
+    let body: Vec<u8> = (0..8 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let url = {
+        let requests = requests.clone();
+        http_server(move |req| {
+            let header = |name| req.header(name).map(str::to_string);
+            requests.lock().unwrap().push([header("range"), header("authorization"), header("user-agent"), header("x-feed")]);
+            range_response(&body, req)
+        })
+    };
+    let options = SourceOptions {
+        read_ahead: ReadAhead::Bytes(CHUNK_SIZE),
+        http: HttpConfig::new().bearer_auth("secret").user_agent("podcasts/1.0").header("X-Feed", "premium"),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new(&format!("{}/episode.mp3", url), None, &options).unwrap();
+    source.seek(SeekFrom::Start(6 * CHUNK_SIZE as u64)).unwrap();
+    source.read_exact(&mut [0u8; 10]).unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1][0].as_deref(), Some("bytes=393216-"));
+    for request in requests.iter() {
+        assert_eq!(request[1].as_deref(), Some("Bearer secret"));
+        assert_eq!(request[2].as_deref(), Some("podcasts/1.0"));
+        assert_eq!(request[3].as_deref(), Some("premium"));
+    }
+}
+
+#[test]
+fn reads_through_custom_transport() {
+    use crate::http::{HttpResponse, HttpTransport};
+
+    type Headers = Vec<(String, String)>;
+
+    struct MemoryTransport {
+        body: Vec<u8>,
+        requests: Mutex<Vec<(Option<u64>, Headers)>>,
+    }
+
+    impl HttpTransport for MemoryTransport {
+        fn get(&self, url: &str, range_start: Option<u64>, headers: &[(String, String)]) -> io::Result<HttpResponse> {
+            assert_eq!(url, "mem://episode.mp3");
+            self.requests.lock().unwrap().push((range_start, headers.to_vec()));
+            let start = range_start.unwrap_or(0) as usize;
+            let mut response_headers = vec![
+                ("Content-Length".to_string(), (self.body.len() - start).to_string()),
+                ("Accept-Ranges".to_string(), "bytes".to_string()),
+            ];
+            if start > 0 {
+                let range = format!("bytes {}-{}/{}", start, self.body.len() - 1, self.body.len());
+                response_headers.push(("Content-Range".to_string(), range));
+            }
+            Ok(HttpResponse {
+                status: if start > 0 { 206 } else { 200 },
+                headers: response_headers,
+                body: Box::new(io::Cursor::new(self.body[start..].to_vec())),
+            })
+        }
+    }
+
+    let body: Vec<u8> = (0..10 * CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    let transport = Arc::new(MemoryTransport { body, requests: Default::default() });
+    let options = SourceOptions {
+        read_ahead: ReadAhead::Bytes(CHUNK_SIZE),
+        http: crate::http::HttpConfig::new().header("X-Feed", "premium"),
+        transport: Some(transport.clone()),
+        ..Default::default()
+    };
+    let mut source = UrlSourceBuf::new("mem://episode.mp3", None, &options).unwrap();
+    assert_eq!(source.byte_len(), Some(10 * CHUNK_SIZE as u64));
+    let pos = 8 * CHUNK_SIZE + 7;
+    source.seek(SeekFrom::Start(pos as u64)).unwrap();
+    let mut buf = vec![0u8; 100];
+    source.read_exact(&mut buf).unwrap();
+    assert!(buf.iter().enumerate().all(|(i, &b)| b == ((pos + i) % 251) as u8));
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].0, None);
+    assert!(requests.iter().any(|(range, _)| *range == Some(8 * CHUNK_SIZE as u64)));
+    let feed = ("X-Feed".to_string(), "premium".to_string());
+    assert!(requests.iter().all(|(_, headers)| headers.contains(&feed)));
+}
 
 #[test]
 fn ureq_range() {
@@ -0,0 +1,84 @@
+#[cfg(feature = "playback")]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "playback")]
+use std::sync::Arc;
+
+/// Duration of a volume change ramp, long enough to avoid zipper noise.
+const RAMP_SECS: f32 = 0.02;
+
+/// Target gain shared between the engine thread and the audio callback.
+#[cfg(feature = "playback")]
+#[derive(Clone)]
+pub struct SharedGain(Arc<AtomicU32>);
+
+#[cfg(feature = "playback")]
+impl SharedGain {
+    pub fn new(gain: f32) -> Self {
+        SharedGain(Arc::new(AtomicU32::new(gain.to_bits())))
+    }
+
+    pub fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Gain which follows its target linearly over `RAMP_SECS` instead of jumping to it.
+pub struct GainRamp {
+    current: f32,
+    step: f32,
+}
+
+impl GainRamp {
+    pub fn new(gain: f32, sample_rate: u32) -> Self {
+        GainRamp {
+            current: gain,
+            step: 1.0 / (RAMP_SECS * sample_rate.max(1) as f32),
+        }
+    }
+
+    /// Gain for the next frame.
+    pub fn next(&mut self, target: f32) -> f32 {
+        if self.current < target {
+            self.current = (self.current + self.step).min(target);
+        } else if self.current > target {
+            self.current = (self.current - self.step).max(target);
+        }
+        self.current
+    }
+
+    /// Apply gain to interleaved `samples` with `channels` samples per frame.
+    pub fn apply(&mut self, target: f32, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let gain = self.next(target);
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+    }
+}
+
+#[test]
+fn gain_ramp_reaches_target_without_overshoot() {
+    // 100 Hz: a full scale ramp takes 2 frames.
+    let mut ramp = GainRamp::new(1.0, 100);
+    assert_eq!(ramp.next(0.0), 0.5);
+    assert_eq!(ramp.next(0.0), 0.0);
+    assert_eq!(ramp.next(0.0), 0.0);
+    assert_eq!(ramp.next(0.25), 0.25);
+
+    let mut samples = [1.0f32; 8];
+    let mut ramp = GainRamp::new(0.0, 100);
+    ramp.apply(1.0, &mut samples, 2);
+    assert_eq!(samples, [0.5, 0.5, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+}
+
+#[cfg(feature = "playback")]
+#[test]
+fn shared_gain_roundtrip() {
+    let gain = SharedGain::new(1.0);
+    let other = gain.clone();
+    other.set(0.3);
+    assert_eq!(gain.get(), 0.3);
+}